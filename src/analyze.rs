@@ -0,0 +1,347 @@
+use crate::cli::SceneOptions;
+use crate::loader::LoadProgress;
+use crate::planner::format_duration;
+use crate::stats::{format_length, FILAMENT_TOLERANCE};
+use crate::travel::{TravelKind, TravelStats};
+use crate::{build_scene, Bounds, Scene};
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// Conditions `analyze` warns about, and can be told to fail on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Check {
+    /// Extruded filament differs from the slicer's stated total
+    FilamentMismatch,
+    /// Extrusions outside the printable area or height from the slicer settings
+    OutsideBed,
+    /// No extrusions between the start and end G-code
+    EmptyPrint,
+    /// Estimated time above `--max-time`
+    TimeLimit,
+    /// Filament weight above `--max-filament`
+    FilamentLimit,
+}
+
+impl Check {
+    pub const ALL: [Check; 5] = [
+        Check::FilamentMismatch,
+        Check::OutsideBed,
+        Check::EmptyPrint,
+        Check::TimeLimit,
+        Check::FilamentLimit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::FilamentMismatch => "filament-mismatch",
+            Check::OutsideBed => "outside-bed",
+            Check::EmptyPrint => "empty-print",
+            Check::TimeLimit => "time-limit",
+            Check::FilamentLimit => "filament-limit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|check| check.name() == name)
+    }
+
+    /// Limits only run when asked for, so breaking one always fails.
+    fn is_limit(self) -> bool {
+        matches!(self, Check::TimeLimit | Check::FilamentLimit)
+    }
+}
+
+/// What `gsoda analyze` was asked to do.
+pub struct AnalyzeOptions {
+    pub file: String,
+    pub json: bool,
+    pub scene: SceneOptions,
+    /// Warnings that make the run fail
+    pub fail_on: Vec<Check>,
+    /// In seconds
+    pub max_time: Option<f32>,
+    /// In g
+    pub max_filament: Option<f32>,
+}
+
+pub struct Warning {
+    pub check: Check,
+    pub message: String,
+}
+
+/// How far outside the printable area an extrusion may reach before it counts, in mm.
+const BED_TOLERANCE: f32 = 0.5;
+
+/// Loads the file without a window and reports on it.
+///
+/// Returns false when a warning in `fail_on`, or a broken limit, should fail the run.
+pub fn run(options: &AnalyzeOptions) -> Result<bool> {
+    let scene = build_scene(&options.file, options.scene, &LoadProgress::default())?;
+    let warnings = check(&scene, options);
+    let passed = !warnings
+        .iter()
+        .any(|w| w.check.is_limit() || options.fail_on.contains(&w.check));
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report(&scene, &warnings, passed))?);
+    } else {
+        print_report(&scene, &warnings, passed);
+    }
+    Ok(passed)
+}
+
+fn check(scene: &Scene, options: &AnalyzeOptions) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut warn = |check, message| warnings.push(Warning { check, message });
+    let stats = &scene.stats;
+
+    if let Some(deviation) = stats.slicer_deviation().filter(|d| d.abs() > FILAMENT_TOLERANCE) {
+        warn(
+            Check::FilamentMismatch,
+            format!(
+                "Extruded {} but the slicer states {} ({:+.1}%)",
+                format_length(stats.total.length),
+                format_length(stats.slicer_length.unwrap_or_default()),
+                deviation * 100.0
+            ),
+        );
+    }
+
+    let bounds = &scene.bounds;
+    if let Some(area) = scene.metadata.printable_area {
+        if bounds.min.x < area.min_x - BED_TOLERANCE
+            || bounds.min.y < area.min_y - BED_TOLERANCE
+            || bounds.max.x > area.max_x + BED_TOLERANCE
+            || bounds.max.y > area.max_y + BED_TOLERANCE
+        {
+            warn(
+                Check::OutsideBed,
+                format!(
+                    "Extrusions span X {:.1}..{:.1} Y {:.1}..{:.1}, outside the printable area X {}..{} Y {}..{}",
+                    bounds.min.x, bounds.max.x, bounds.min.y, bounds.max.y, area.min_x, area.max_x, area.min_y, area.max_y
+                ),
+            );
+        }
+    }
+    if let Some(height) = scene.metadata.printable_height.filter(|&h| h > 0.0) {
+        if bounds.max.z > height + BED_TOLERANCE {
+            warn(
+                Check::OutsideBed,
+                format!("Extrusions reach Z {:.2}, above the printable height {}", bounds.max.z, height),
+            );
+        }
+    }
+
+    let toolpath = &scene.toolpath;
+    if !scene.print_range.clone().any(|i| toolpath.segments[i].is_extrusion()) {
+        warn(Check::EmptyPrint, "No extrusions between the start and end G-code".to_owned());
+    }
+
+    if let Some(limit) = options.max_time.filter(|&limit| scene.print_time > limit) {
+        warn(
+            Check::TimeLimit,
+            format!("Estimated time {} exceeds {}", format_duration(scene.print_time), format_duration(limit)),
+        );
+    }
+    if let Some(limit) = options.max_filament {
+        if !stats.has_weight {
+            warn(Check::FilamentLimit, "Filament weight unknown: the file has no filament density".to_owned());
+        } else if stats.total.weight > limit {
+            warn(
+                Check::FilamentLimit,
+                format!("Filament weight {:.2}g exceeds {}g", stats.total.weight, limit),
+            );
+        }
+    }
+    warnings
+}
+
+/// Move counts, distances, travel kinds and layers of the print section, so they cover the same
+/// moves as the reported bounds.
+struct Section {
+    extrusions: usize,
+    travels: usize,
+    extrusion_length: f32,
+    travel_distance: f32,
+    travel: TravelStats,
+    /// Indices of the layers with extrusions in the section
+    layers: Vec<usize>,
+}
+
+fn section(scene: &Scene) -> Section {
+    let toolpath = &scene.toolpath;
+    let range = scene.print_range.clone();
+    let mut section = Section {
+        extrusions: 0,
+        travels: 0,
+        extrusion_length: 0.0,
+        travel_distance: 0.0,
+        travel: TravelStats::compute(toolpath, &scene.layers, range.clone()),
+        layers: Vec::new(),
+    };
+    for index in range.clone() {
+        if toolpath.segments[index].is_extrusion() {
+            section.extrusions += 1;
+            section.extrusion_length += toolpath.length(index);
+        } else {
+            section.travels += 1;
+            section.travel_distance += toolpath.length(index);
+        }
+    }
+    section.layers = (0..scene.layers.len())
+        .filter(|&i| {
+            let layer = &scene.layers[i].range;
+            (layer.start.max(range.start)..layer.end.min(range.end)).any(|i| toolpath.segments[i].is_extrusion())
+        })
+        .collect();
+    section
+}
+
+impl Section {
+    /// Z-hops in each of the section's layers.
+    fn z_hops_per_layer(&self) -> Vec<u32> {
+        self.layers.iter().map(|&i| self.travel.z_hops_per_layer[i]).collect()
+    }
+}
+
+/// Rounds to a micron (or a thousandth of whatever the unit is), so the JSON isn't cluttered
+/// with `f32` noise like `121.86399841308594`.
+fn round(value: f32) -> f64 {
+    (value as f64 * 1000.0).round() / 1000.0
+}
+
+fn bounds_json(bounds: &Bounds) -> Value {
+    json!({
+        "min": [round(bounds.min.x), round(bounds.min.y), round(bounds.min.z)],
+        "max": [round(bounds.max.x), round(bounds.max.y), round(bounds.max.z)],
+    })
+}
+
+fn report(scene: &Scene, warnings: &[Warning], passed: bool) -> Value {
+    let section = section(scene);
+    let stats = &scene.stats;
+    let tools: Vec<_> = scene
+        .legend_tools
+        .iter()
+        .map(|&tool| {
+            let (usage, purge) = (&stats.per_tool[tool as usize], &stats.purge_per_tool[tool as usize]);
+            json!({
+                "tool": tool,
+                "length_mm": round(usage.length),
+                "volume_mm3": round(usage.volume),
+                "weight_g": stats.has_weight.then(|| round(usage.weight)),
+                "purge_length_mm": round(purge.length),
+                "purge_volume_mm3": round(purge.volume),
+                "changes": scene.tool_changes.counts[tool as usize],
+            })
+        })
+        .collect();
+    let travel = &section.travel;
+    let travel_kinds: Vec<_> = TravelKind::ALL
+        .iter()
+        .map(|&kind| {
+            json!({
+                "kind": kind.name(),
+                "moves": travel.moves[kind as usize],
+                "distance_mm": round(travel.distance[kind as usize]),
+            })
+        })
+        .collect();
+    json!({
+        "file": scene.filename,
+        "slicer": scene.metadata.slicer,
+        "segments": {
+            "total": scene.print_range.len(),
+            "extrusions": section.extrusions,
+            "travels": section.travels,
+        },
+        "file_segments": scene.toolpath.len(),
+        "print_section": { "start": scene.print_range.start, "end": scene.print_range.end },
+        "layers": section.layers.len(),
+        "bounds": bounds_json(&scene.print_bounds),
+        "file_bounds": bounds_json(&scene.bounds),
+        "extrusion_length_mm": round(section.extrusion_length),
+        "travel_distance_mm": round(section.travel_distance),
+        "travel": {
+            "kinds": travel_kinds,
+            "z_hops": travel.z_hops(),
+            "z_hops_per_layer": section.z_hops_per_layer(),
+        },
+        "filament": {
+            "length_mm": round(stats.total.length),
+            "volume_mm3": round(stats.total.volume),
+            "weight_g": stats.has_weight.then(|| round(stats.total.weight)),
+            "cost": stats.has_cost.then(|| round(stats.total.cost)),
+            "slicer_length_mm": stats.slicer_length.map(round),
+        },
+        "retractions": scene.retractions.iter().filter(|r| r.kind.is_retract()).count(),
+        "unretractions": scene.retractions.iter().filter(|r| !r.kind.is_retract()).count(),
+        "tools": tools,
+        "tool_changes": scene.tool_changes.total(),
+        "time": {
+            "estimated_s": round(scene.print_time),
+            "slicer_s": scene.slicer_time.map(round),
+        },
+        "warnings": warnings
+            .iter()
+            .map(|w| json!({ "check": w.check.name(), "message": w.message }))
+            .collect::<Vec<_>>(),
+        "passed": passed,
+    })
+}
+
+fn print_report(scene: &Scene, warnings: &[Warning], passed: bool) {
+    let section = section(scene);
+    let bounds = &scene.print_bounds;
+    println!("File: {}", scene.filename);
+    println!(
+        "Segments: {} ({} extrusions, {} travels) of {} in the file",
+        scene.print_range.len(),
+        section.extrusions,
+        section.travels,
+        scene.toolpath.len()
+    );
+    println!("Print section: segments {}..{}", scene.print_range.start, scene.print_range.end);
+    println!("Layers: {}", section.layers.len());
+    println!(
+        "Bounds: ({:.1}, {:.1}, {:.1}) to ({:.1}, {:.1}, {:.1})",
+        bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z
+    );
+    println!("Extrusion length: {}", format_length(section.extrusion_length));
+    println!("Travel distance: {}", format_length(section.travel_distance));
+    let travel = &section.travel;
+    for kind in travel.present() {
+        println!(
+            "  {}: {} moves, {}",
+            kind.name(),
+            travel.moves[kind as usize],
+            format_length(travel.distance[kind as usize])
+        );
+    }
+    println!(
+        "Z-hops: {} (at most {} in a layer)",
+        travel.z_hops(),
+        section.z_hops_per_layer().into_iter().max().unwrap_or(0)
+    );
+    let retracts = scene.retractions.iter().filter(|r| r.kind.is_retract()).count();
+    println!("Retractions: {} ({} unretractions)", retracts, scene.retractions.len() - retracts);
+    println!("Filament: {}", scene.stats.describe(&scene.stats.total));
+    if scene.legend_tools.len() > 1 || scene.tool_changes.total() > 0 {
+        println!("Tool changes: {}", scene.tool_changes.total());
+        for &tool in &scene.legend_tools {
+            let tool = tool as usize;
+            println!(
+                "  T{}: {}, purge {}, {} changes",
+                tool,
+                scene.stats.describe(&scene.stats.per_tool[tool]),
+                scene.stats.describe(&scene.stats.purge_per_tool[tool]),
+                scene.tool_changes.counts[tool]
+            );
+        }
+    }
+    println!("{}", scene.time_text);
+    for warning in warnings {
+        println!("Warning [{}]: {}", warning.check.name(), warning.message);
+    }
+    println!("{}", if passed { "PASSED" } else { "FAILED" });
+}
@@ -0,0 +1,203 @@
+use crate::Vec3D;
+use std::f32::consts::TAU;
+
+/// Maximum distance between a chord and the true arc, in mm.
+const ARC_TOLERANCE: f32 = 0.01;
+
+/// How much longer than the diameter an R-form chord may be and still make a half circle, in mm,
+/// as endpoints rounded to a few decimals leave semicircles slightly short of their radius.
+const RADIUS_SLACK: f32 = 0.01;
+
+/// Upper bound on chords per arc so a malformed radius can't explode the segment count.
+const MAX_ARC_CHORDS: usize = 1024;
+
+/// Working plane for G2/G3, selected with G17/G18/G19.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArcPlane {
+    /// G17: arcs in XY, Z is the helical axis (offsets I/J)
+    XY,
+    /// G18: arcs in ZX, Y is the helical axis (offsets K/I)
+    ZX,
+    /// G19: arcs in YZ, X is the helical axis (offsets J/K)
+    YZ,
+}
+
+impl ArcPlane {
+    /// Splits a point into (first plane axis, second plane axis, linear axis).
+    fn split(self, p: Vec3D) -> (f32, f32, f32) {
+        match self {
+            ArcPlane::XY => (p.x, p.y, p.z),
+            ArcPlane::ZX => (p.z, p.x, p.y),
+            ArcPlane::YZ => (p.y, p.z, p.x),
+        }
+    }
+
+    fn join(self, a: f32, b: f32, linear: f32) -> Vec3D {
+        match self {
+            ArcPlane::XY => Vec3D::new(a, b, linear),
+            ArcPlane::ZX => Vec3D::new(b, linear, a),
+            ArcPlane::YZ => Vec3D::new(linear, a, b),
+        }
+    }
+}
+
+/// How the arc center is specified.
+#[derive(Clone, Copy, Debug)]
+pub enum ArcCenter {
+    /// I/J/K offsets from the start point, in XYZ order
+    Offset(Vec3D),
+    /// R form: positive for arcs up to 180 degrees, negative for longer ones
+    Radius(f32),
+}
+
+/// Tessellates a G2/G3 arc into chord end points, excluding `start` and ending exactly at `end`.
+///
+/// Returns `None` when the arc is geometrically impossible (e.g. R shorter than half the chord
+/// by more than [`RADIUS_SLACK`]), in which case the caller should fall back to a straight move.
+pub fn tessellate_arc(
+    start: Vec3D,
+    end: Vec3D,
+    center: ArcCenter,
+    clockwise: bool,
+    plane: ArcPlane,
+) -> Option<Vec<Vec3D>> {
+    let (start_a, start_b, start_linear) = plane.split(start);
+    let (end_a, end_b, end_linear) = plane.split(end);
+
+    // Center relative to the start point, in plane coordinates
+    let (offset_a, offset_b) = match center {
+        ArcCenter::Offset(offset) => {
+            let (a, b, _) = plane.split(offset);
+            (a, b)
+        }
+        ArcCenter::Radius(radius) => {
+            let dx = end_a - start_a;
+            let dy = end_b - start_b;
+            let chord = dx.hypot(dy);
+            if chord == 0.0 || chord > 2.0 * radius.abs() + RADIUS_SLACK {
+                return None;
+            }
+            // Like Marlin, a chord a hair longer than the diameter is a half circle
+            let h_sq = (4.0 * radius * radius - dx * dx - dy * dy).max(0.0);
+            let mut h_x2_div_d = -h_sq.sqrt() / chord;
+            if !clockwise {
+                h_x2_div_d = -h_x2_div_d;
+            }
+            if radius < 0.0 {
+                h_x2_div_d = -h_x2_div_d;
+            }
+            (0.5 * (dx - dy * h_x2_div_d), 0.5 * (dy + dx * h_x2_div_d))
+        }
+    };
+
+    let center_a = start_a + offset_a;
+    let center_b = start_b + offset_b;
+    let radius = offset_a.hypot(offset_b);
+    if radius == 0.0 {
+        return None;
+    }
+
+    // Angle swept from start to end, in the commanded direction; a zero sweep is a full circle
+    let (start_va, start_vb) = (-offset_a, -offset_b);
+    let (end_va, end_vb) = (end_a - center_a, end_b - center_b);
+    let mut sweep = (start_va * end_vb - start_vb * end_va).atan2(start_va * end_va + start_vb * end_vb);
+    if clockwise {
+        if sweep >= 0.0 {
+            sweep -= TAU;
+        }
+    } else if sweep <= 0.0 {
+        sweep += TAU;
+    }
+
+    let max_step = if radius > ARC_TOLERANCE {
+        2.0 * (1.0 - ARC_TOLERANCE / radius).acos()
+    } else {
+        TAU
+    };
+    let chords = ((sweep.abs() / max_step).ceil() as usize).clamp(1, MAX_ARC_CHORDS);
+
+    let start_angle = start_vb.atan2(start_va);
+    let mut points = Vec::with_capacity(chords);
+    for i in 1..chords {
+        let t = i as f32 / chords as f32;
+        let angle = start_angle + sweep * t;
+        points.push(plane.join(
+            center_a + radius * angle.cos(),
+            center_b + radius * angle.sin(),
+            start_linear + (end_linear - start_linear) * t,
+        ));
+    }
+    points.push(end);
+
+    Some(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_on_circle(points: &[Vec3D], center: Vec3D, radius: f32) {
+        for p in points {
+            let distance = (p.x - center.x).hypot(p.y - center.y);
+            assert!((distance - radius).abs() < 1e-3, "{:?} is {} from the center", p, distance);
+        }
+    }
+
+    #[test]
+    fn offset_quarter_circle() {
+        let (start, end) = (Vec3D::new(10.0, 0.0, 0.0), Vec3D::new(0.0, 10.0, 0.0));
+        let center = ArcCenter::Offset(Vec3D::new(-10.0, 0.0, 0.0));
+        let points = tessellate_arc(start, end, center, false, ArcPlane::XY).unwrap();
+        assert!(points.len() > 1);
+        assert_eq!(points.last(), Some(&end));
+        assert_on_circle(&points, Vec3D::zero(), 10.0);
+        assert!(points.iter().all(|p| p.x >= -1e-3 && p.y >= -1e-3));
+    }
+
+    #[test]
+    fn radius_rounded_semicircle() {
+        // Endpoints written with 3 decimals leave the chord a hair longer than the diameter
+        let (start, end) = (Vec3D::new(0.0, 0.0, 0.2), Vec3D::new(20.001, 0.0, 0.2));
+        let points = tessellate_arc(start, end, ArcCenter::Radius(10.0), true, ArcPlane::XY).unwrap();
+        let top = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+        assert!((top - 10.0).abs() < 0.01, "peaks at {}", top);
+    }
+
+    #[test]
+    fn radius_too_short() {
+        let (start, end) = (Vec3D::new(0.0, 0.0, 0.0), Vec3D::new(20.0, 0.0, 0.0));
+        assert!(tessellate_arc(start, end, ArcCenter::Radius(9.9), true, ArcPlane::XY).is_none());
+    }
+
+    #[test]
+    fn radius_sign_picks_the_long_way() {
+        let (start, end) = (Vec3D::new(0.0, 0.0, 0.0), Vec3D::new(10.0, 10.0, 0.0));
+        let short = tessellate_arc(start, end, ArcCenter::Radius(10.0), true, ArcPlane::XY).unwrap();
+        let long = tessellate_arc(start, end, ArcCenter::Radius(-10.0), true, ArcPlane::XY).unwrap();
+        assert!(long.len() > 2 * short.len());
+    }
+
+    #[test]
+    fn helix_full_circle() {
+        let (start, end) = (Vec3D::new(10.0, 0.0, 0.0), Vec3D::new(10.0, 0.0, 1.0));
+        let center = ArcCenter::Offset(Vec3D::new(-10.0, 0.0, 0.0));
+        let points = tessellate_arc(start, end, center, false, ArcPlane::XY).unwrap();
+        assert_on_circle(&points, Vec3D::zero(), 10.0);
+        let half = &points[points.len() / 2 - 1];
+        assert!((half.x + 10.0).abs() < 0.1 && (half.z - 0.5).abs() < 0.02, "{:?}", half);
+        assert_eq!(points.last(), Some(&end));
+    }
+
+    #[test]
+    fn zx_plane() {
+        // G18: the arc turns in ZX around the center at X0 Z0, with Y untouched
+        let (start, end) = (Vec3D::new(10.0, 5.0, 0.0), Vec3D::new(0.0, 5.0, 10.0));
+        let center = ArcCenter::Offset(Vec3D::new(-10.0, 0.0, 0.0));
+        let points = tessellate_arc(start, end, center, true, ArcPlane::ZX).unwrap();
+        for p in &points {
+            assert_eq!(p.y, 5.0);
+            assert!((p.x.hypot(p.z) - 10.0).abs() < 1e-3);
+            assert!(p.x >= -1e-3 && p.z >= -1e-3);
+        }
+    }
+}
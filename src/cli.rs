@@ -0,0 +1,246 @@
+use crate::analyze::{AnalyzeOptions, Check};
+use crate::planner::parse_duration;
+use crate::profile::Region;
+use crate::render::{RenderOptions, View};
+use anyhow::{anyhow, bail, Result};
+
+/// What the program was asked to do.
+pub enum Command {
+    /// Open the viewer window
+    View(Options),
+    /// Print the slicer settings found in the file (`--info`)
+    Info { file: String },
+    /// Report on the file without a window (`analyze`)
+    Analyze(AnalyzeOptions),
+    /// Write the embedded thumbnails to image files, next to the G-code unless `output_dir` is given
+    Thumbnails { file: String, output_dir: Option<String> },
+    /// Draw the toolpath to a PNG without a window (`render`)
+    Render(RenderOptions),
+}
+
+impl Command {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("thumbnails") => {
+                args.next();
+                return parse_thumbnails(args);
+            }
+            Some("analyze") => {
+                args.next();
+                return parse_analyze(args);
+            }
+            Some("render") => {
+                args.next();
+                return parse_render(args);
+            }
+            _ => {}
+        }
+        let (info, args): (Vec<_>, Vec<_>) = args.partition(|arg| arg == "--info");
+        let options = Options::parse(args)?;
+        if info.is_empty() {
+            Ok(Self::View(options))
+        } else {
+            Ok(Self::Info { file: options.file })
+        }
+    }
+}
+
+fn parse_thumbnails(args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut file = None;
+    let mut output_dir = None;
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output_dir = Some(args.next().ok_or_else(|| anyhow!("{} needs a directory", arg))?);
+            }
+            _ if arg.starts_with('-') => bail!("Unknown option: {}", arg),
+            _ if file.is_none() => file = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+
+    Ok(Command::Thumbnails {
+        file: file.ok_or_else(|| anyhow!("No G-code file given"))?,
+        output_dir,
+    })
+}
+
+/// Command-line options for the viewer.
+pub struct Options {
+    pub file: String,
+    /// Dim the start/end sequences and frame the print itself; off shows the raw file
+    pub filter: bool,
+    pub scene: SceneOptions,
+}
+
+/// Options that change how a file is turned into a scene.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SceneOptions {
+    /// Purge region to use instead of the printer profile's
+    pub purge_region: Option<Region>,
+    /// Filament price per kg to use instead of the file's `filament_cost`
+    pub filament_price: Option<f32>,
+}
+
+impl SceneOptions {
+    /// Takes `arg` and its value from `args` if it is a scene option; false if it isn't one.
+    fn parse_arg(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> Result<bool> {
+        match arg {
+            "--purge-region" => {
+                let value = args.next().ok_or_else(|| anyhow!("--purge-region needs X0,Y0,X1,Y1"))?;
+                self.purge_region = Some(
+                    Region::parse(&value)
+                        .ok_or_else(|| anyhow!("Invalid purge region '{}', expected X0,Y0,X1,Y1", value))?,
+                );
+            }
+            "--filament-price" => {
+                let value = args.next().ok_or_else(|| anyhow!("--filament-price needs a price per kg"))?;
+                self.filament_price = Some(parse_number(&value, "filament price")?);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl Options {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut file = None;
+        let mut filter = true;
+        let mut scene = SceneOptions::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--no-filter" => filter = false,
+                _ if scene.parse_arg(&arg, &mut args)? => {}
+                _ if arg.starts_with("--") => bail!("Unknown option: {}", arg),
+                _ if file.is_none() => file = Some(arg),
+                _ => bail!("Unexpected argument: {}", arg),
+            }
+        }
+
+        Ok(Self {
+            file: file.ok_or_else(|| anyhow!("No G-code file given"))?,
+            filter,
+            scene,
+        })
+    }
+}
+
+fn parse_analyze(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut file = None;
+    let mut options = AnalyzeOptions {
+        file: String::new(),
+        json: false,
+        scene: SceneOptions::default(),
+        fail_on: Vec::new(),
+        max_time: None,
+        max_filament: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--fail-on" => {
+                let value = args.next().ok_or_else(|| anyhow!("--fail-on needs a list of checks"))?;
+                options.fail_on = parse_checks(&value)?;
+            }
+            "--max-time" => {
+                let value = args.next().ok_or_else(|| anyhow!("--max-time needs a duration"))?;
+                // Plain seconds or a slicer-style duration such as `1h 30m`
+                let seconds = value.parse().ok().or_else(|| parse_duration(&value));
+                options.max_time =
+                    Some(seconds.ok_or_else(|| anyhow!("Invalid duration '{}', expected seconds or e.g. '1h 30m'", value))?);
+            }
+            "--max-filament" => {
+                let value = args.next().ok_or_else(|| anyhow!("--max-filament needs a weight in g"))?;
+                options.max_filament = Some(parse_number(&value, "filament weight")?);
+            }
+            _ if options.scene.parse_arg(&arg, &mut args)? => {}
+            _ if arg.starts_with("--") => bail!("Unknown option: {}", arg),
+            _ if file.is_none() => file = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+
+    options.file = file.ok_or_else(|| anyhow!("No G-code file given"))?;
+    Ok(Command::Analyze(options))
+}
+
+fn parse_render(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut file = None;
+    let mut output = None;
+    let mut options = RenderOptions {
+        file: String::new(),
+        output: String::new(),
+        view: View::Iso,
+        width: 800,
+        height: 600,
+        lines: false,
+        travels: false,
+        filter: true,
+        scene: SceneOptions::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output = Some(args.next().ok_or_else(|| anyhow!("{} needs a PNG file", arg))?);
+            }
+            "--view" => {
+                let value = args.next().ok_or_else(|| anyhow!("--view needs a view name"))?;
+                options.view = View::from_name(&value).ok_or_else(|| {
+                    let names: Vec<_> = View::ALL.iter().map(|v| v.name()).collect();
+                    anyhow!("Unknown view '{}', expected one of: {}", value, names.join(", "))
+                })?;
+            }
+            "--size" => {
+                let value = args.next().ok_or_else(|| anyhow!("--size needs WIDTHxHEIGHT"))?;
+                (options.width, options.height) = value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .filter(|&(w, h)| (1..=8192).contains(&w) && (1..=8192).contains(&h))
+                    .ok_or_else(|| anyhow!("Invalid size '{}', expected e.g. 800x600", value))?;
+            }
+            "--lines" => options.lines = true,
+            "--travels" => options.travels = true,
+            "--no-filter" => options.filter = false,
+            _ if options.scene.parse_arg(&arg, &mut args)? => {}
+            _ if arg.starts_with('-') => bail!("Unknown option: {}", arg),
+            _ if file.is_none() => file = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+
+    options.file = file.ok_or_else(|| anyhow!("No G-code file given"))?;
+    options.output = output.ok_or_else(|| anyhow!("No output file given (-o out.png)"))?;
+    Ok(Command::Render(options))
+}
+
+/// Comma-separated check names, or `all`.
+fn parse_checks(value: &str) -> Result<Vec<Check>> {
+    if value == "all" {
+        return Ok(Check::ALL.to_vec());
+    }
+    value
+        .split(',')
+        .map(|name| {
+            Check::from_name(name.trim()).ok_or_else(|| {
+                let names: Vec<_> = Check::ALL.iter().map(|c| c.name()).collect();
+                anyhow!("Unknown check '{}', expected one of: {}, all", name, names.join(", "))
+            })
+        })
+        .collect()
+}
+
+fn parse_number(value: &str, what: &str) -> Result<f32> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid {} '{}', expected a number", what, value))
+}
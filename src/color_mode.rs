@@ -0,0 +1,167 @@
+use crate::{Layer, Toolpath};
+use std::f32::consts::PI;
+
+/// What the extrusion color encodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    Feature,
+    /// Filament color of the active tool
+    Tool,
+    Feedrate,
+    Flow,
+    Width,
+    LayerHeight,
+    Fan,
+    Temperature,
+    LayerTime,
+}
+
+impl ColorMode {
+    pub fn next(self) -> Self {
+        match self {
+            ColorMode::Feature => ColorMode::Tool,
+            ColorMode::Tool => ColorMode::Feedrate,
+            ColorMode::Feedrate => ColorMode::Flow,
+            ColorMode::Flow => ColorMode::Width,
+            ColorMode::Width => ColorMode::LayerHeight,
+            ColorMode::LayerHeight => ColorMode::Fan,
+            ColorMode::Fan => ColorMode::Temperature,
+            ColorMode::Temperature => ColorMode::LayerTime,
+            ColorMode::LayerTime => ColorMode::Feature,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::Feature => "Feature type",
+            ColorMode::Tool => "Tool",
+            ColorMode::Feedrate => "Speed",
+            ColorMode::Flow => "Volumetric flow",
+            ColorMode::Width => "Line width",
+            ColorMode::LayerHeight => "Layer height",
+            ColorMode::Fan => "Fan speed",
+            ColorMode::Temperature => "Temperature",
+            ColorMode::LayerTime => "Layer time",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            ColorMode::Feature | ColorMode::Tool => "",
+            ColorMode::Feedrate => "mm/s",
+            ColorMode::Flow => "mm3/s",
+            ColorMode::Width | ColorMode::LayerHeight => "mm",
+            ColorMode::Fan => "%",
+            ColorMode::Temperature => "C",
+            ColorMode::LayerTime => "s",
+        }
+    }
+}
+
+/// Per-segment values for a gradient color mode, plus the min/max over extrusion moves.
+pub struct ColorScale {
+    pub values: Vec<f32>,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ColorScale {
+    /// Evaluates `mode` for every segment; `None` for [`ColorMode::Feature`] and
+    /// [`ColorMode::Tool`], which use fixed colors.
+    pub fn compute(
+        mode: ColorMode,
+        toolpath: &Toolpath,
+        layers: &[Layer],
+        filament_diameter: f32,
+    ) -> Option<Self> {
+        if matches!(mode, ColorMode::Feature | ColorMode::Tool) {
+            return None;
+        }
+
+        let filament_area = PI * (filament_diameter * 0.5).powi(2);
+        let mut values = vec![0.0; toolpath.len()];
+        for layer in layers {
+            for index in layer.range.clone() {
+                let seg = &toolpath.segments[index];
+                let length = toolpath.length(index);
+                let (width, height) = seg.bead_size(length, layer.height, filament_area);
+                values[index] = match mode {
+                    ColorMode::Feature | ColorMode::Tool => 0.0,
+                    ColorMode::Feedrate => seg.feedrate,
+                    ColorMode::Flow => seg.e * filament_area * seg.feedrate / length,
+                    ColorMode::Width => width,
+                    ColorMode::LayerHeight => height,
+                    ColorMode::Fan => seg.fan as f32,
+                    ColorMode::Temperature => seg.temperature as f32,
+                    ColorMode::LayerTime => layer.time,
+                };
+            }
+        }
+
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+        for (value, seg) in values.iter().zip(&toolpath.segments) {
+            if seg.is_extrusion() && value.is_finite() {
+                min = min.min(*value);
+                max = max.max(*value);
+            }
+        }
+        if min > max {
+            min = 0.0;
+            max = 0.0;
+        }
+
+        Some(Self { values, min, max })
+    }
+
+    /// Position of `value` within the range, 0 to 1.
+    pub fn normalize(&self, value: f32) -> f32 {
+        if self.max > self.min {
+            ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.5
+        }
+    }
+}
+
+/// Where extrusions get their base color from.
+#[derive(Clone, Copy)]
+pub enum Coloring<'a> {
+    /// Fixed color per feature type
+    Feature,
+    /// Filament color per tool number
+    Tool(&'a [(u8, u8, u8)]),
+    /// Gradient over the color mode's value
+    Gradient(&'a ColorScale),
+}
+
+impl<'a> Coloring<'a> {
+    /// The coloring `mode` calls for; `scale` is its [`ColorScale`], if it has one.
+    pub fn new(mode: ColorMode, scale: Option<&'a ColorScale>, tool_colors: &'a [(u8, u8, u8)]) -> Self {
+        match (mode, scale) {
+            (_, Some(scale)) => Coloring::Gradient(scale),
+            (ColorMode::Tool, None) => Coloring::Tool(tool_colors),
+            _ => Coloring::Feature,
+        }
+    }
+}
+
+/// Blue → cyan → green → yellow → red gradient for `t` in 0 to 1.
+pub fn gradient(t: f32) -> (u8, u8, u8) {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (40.0, 70.0, 255.0),
+        (0.0, 210.0, 230.0),
+        (60.0, 220.0, 60.0),
+        (250.0, 230.0, 40.0),
+        (240.0, 50.0, 40.0),
+    ];
+    let scaled = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    let f = scaled - i as f32;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    (
+        (a.0 + (b.0 - a.0) * f) as u8,
+        (a.1 + (b.1 - a.1) * f) as u8,
+        (a.2 + (b.2 - a.2) * f) as u8,
+    )
+}
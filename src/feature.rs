@@ -0,0 +1,128 @@
+/// Print region a segment belongs to, taken from slicer `; FEATURE:` / `;TYPE:` annotations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeatureType {
+    OuterWall,
+    InnerWall,
+    OverhangWall,
+    SparseInfill,
+    SolidInfill,
+    TopSurface,
+    BottomSurface,
+    Bridge,
+    GapFill,
+    Skirt,
+    Brim,
+    Support,
+    SupportInterface,
+    PrimeTower,
+    Ironing,
+    Custom,
+    /// No annotation seen yet, or a label we don't recognise
+    Unknown,
+}
+
+impl FeatureType {
+    pub const COUNT: usize = 17;
+
+    pub const ALL: [FeatureType; FeatureType::COUNT] = [
+        FeatureType::OuterWall,
+        FeatureType::InnerWall,
+        FeatureType::OverhangWall,
+        FeatureType::SparseInfill,
+        FeatureType::SolidInfill,
+        FeatureType::TopSurface,
+        FeatureType::BottomSurface,
+        FeatureType::Bridge,
+        FeatureType::GapFill,
+        FeatureType::Skirt,
+        FeatureType::Brim,
+        FeatureType::Support,
+        FeatureType::SupportInterface,
+        FeatureType::PrimeTower,
+        FeatureType::Ironing,
+        FeatureType::Custom,
+        FeatureType::Unknown,
+    ];
+
+    /// Parses the feature annotation from a comment body (text after `;`), if it is one.
+    ///
+    /// Handles BambuStudio/Orca `FEATURE: Outer wall`, PrusaSlicer `TYPE:External perimeter`
+    /// and Cura `TYPE:WALL-OUTER`.
+    pub fn from_comment(comment: &str) -> Option<Self> {
+        let comment = comment.trim_start();
+        let label = comment
+            .strip_prefix("FEATURE:")
+            .or_else(|| comment.strip_prefix("TYPE:"))?;
+        Some(Self::from_label(label))
+    }
+
+    fn from_label(label: &str) -> Self {
+        let label = label.trim().to_ascii_lowercase().replace(['-', '_'], " ");
+        match label.as_str() {
+            "outer wall" | "external perimeter" | "wall outer" => FeatureType::OuterWall,
+            "inner wall" | "perimeter" | "wall inner" => FeatureType::InnerWall,
+            "overhang wall" | "overhang perimeter" => FeatureType::OverhangWall,
+            "sparse infill" | "internal infill" | "fill" => FeatureType::SparseInfill,
+            "internal solid infill" | "solid infill" | "floating vertical shell" | "skin" => {
+                FeatureType::SolidInfill
+            }
+            "top surface" | "top solid infill" => FeatureType::TopSurface,
+            "bottom surface" => FeatureType::BottomSurface,
+            "bridge" | "internal bridge" | "bridge infill" => FeatureType::Bridge,
+            "gap infill" | "gap fill" => FeatureType::GapFill,
+            "skirt" | "skirt/brim" => FeatureType::Skirt,
+            "brim" => FeatureType::Brim,
+            "support" | "support material" | "support transition" => FeatureType::Support,
+            "support interface" | "support material interface" => FeatureType::SupportInterface,
+            "prime tower" | "wipe tower" => FeatureType::PrimeTower,
+            "ironing" => FeatureType::Ironing,
+            "custom" => FeatureType::Custom,
+            _ => FeatureType::Unknown,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FeatureType::OuterWall => "Outer wall",
+            FeatureType::InnerWall => "Inner wall",
+            FeatureType::OverhangWall => "Overhang wall",
+            FeatureType::SparseInfill => "Sparse infill",
+            FeatureType::SolidInfill => "Solid infill",
+            FeatureType::TopSurface => "Top surface",
+            FeatureType::BottomSurface => "Bottom surface",
+            FeatureType::Bridge => "Bridge",
+            FeatureType::GapFill => "Gap fill",
+            FeatureType::Skirt => "Skirt",
+            FeatureType::Brim => "Brim",
+            FeatureType::Support => "Support",
+            FeatureType::SupportInterface => "Support interface",
+            FeatureType::PrimeTower => "Prime tower",
+            FeatureType::Ironing => "Ironing",
+            FeatureType::Custom => "Custom",
+            FeatureType::Unknown => "Other",
+        }
+    }
+
+    /// Base color for the feature, before lighting and height shading.
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            FeatureType::OuterWall => (255, 125, 56),
+            FeatureType::InnerWall => (255, 230, 77),
+            FeatureType::OverhangWall => (38, 102, 255),
+            FeatureType::SparseInfill => (176, 48, 41),
+            FeatureType::SolidInfill => (150, 84, 204),
+            FeatureType::TopSurface => (240, 64, 64),
+            FeatureType::BottomSurface => (102, 92, 199),
+            FeatureType::Bridge => (77, 128, 186),
+            FeatureType::GapFill => (255, 255, 255),
+            FeatureType::Skirt => (0, 135, 135),
+            FeatureType::Brim => (0, 186, 148),
+            FeatureType::Support => (0, 255, 0),
+            FeatureType::SupportInterface => (0, 128, 0),
+            FeatureType::PrimeTower => (179, 227, 171),
+            FeatureType::Ironing => (255, 140, 105),
+            FeatureType::Custom => (94, 209, 148),
+            FeatureType::Unknown => (100, 200, 255),
+        }
+    }
+}
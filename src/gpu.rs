@@ -0,0 +1,302 @@
+use macroquad::miniquad::{
+    BlendFactor, BlendState, BlendValue, BufferId, BufferLayout, BufferSource, BufferType,
+    BufferUsage, Bindings, Comparison, Equation, Pipeline, PipelineParams, PrimitiveType,
+    RenderingBackend, ShaderMeta, ShaderSource, UniformBlockLayout, UniformDesc, UniformType,
+    UniformsSource, VertexAttribute, VertexFormat,
+};
+use macroquad::prelude::*;
+use std::ops::Range;
+
+/// Compact vertex for the static toolpath buffers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GpuVertex {
+    pub position: [f32; 3],
+    pub color: [u8; 4],
+}
+
+impl GpuVertex {
+    pub fn new(position: Vec3, color: Color) -> Self {
+        Self {
+            position: position.to_array(),
+            color: [
+                (color.r * 255.0) as u8,
+                (color.g * 255.0) as u8,
+                (color.b * 255.0) as u8,
+                (color.a * 255.0) as u8,
+            ],
+        }
+    }
+}
+
+/// CPU-side geometry for a set of segments, grouped per segment in ascending order.
+#[derive(Default)]
+pub struct Geometry {
+    pub vertices: Vec<GpuVertex>,
+    pub indices: Vec<u32>,
+    /// Segment index of every group
+    segments: Vec<usize>,
+    /// Offset of each group's first index, plus the total at the end once uploaded
+    index_starts: Vec<usize>,
+}
+
+impl Geometry {
+    /// Starts the group of primitives for `segment`; indices pushed afterwards belong to it.
+    pub fn begin_segment(&mut self, segment: usize) {
+        self.segments.push(segment);
+        self.index_starts.push(self.indices.len());
+    }
+
+    /// Index of the next vertex to be pushed, for building index lists.
+    pub fn base(&self) -> u32 {
+        self.vertices.len() as u32
+    }
+
+    /// Indices of the groups whose segment falls inside `range`.
+    pub fn indices_in(&self, range: &Range<usize>) -> &[u32] {
+        let first = self.segments.partition_point(|&s| s < range.start);
+        let last = self.segments.partition_point(|&s| s < range.end);
+        let start = |group: usize| self.index_starts.get(group).copied().unwrap_or(self.indices.len());
+        &self.indices[start(first)..start(last)]
+    }
+}
+
+/// Geometry uploaded once into immutable GPU buffers, drawn by segment range.
+pub struct GpuBatch {
+    bindings: Bindings,
+    segments: Vec<usize>,
+    index_starts: Vec<usize>,
+}
+
+impl GpuBatch {
+    fn upload(ctx: &mut dyn RenderingBackend, mut geometry: Geometry) -> Option<Self> {
+        if geometry.indices.is_empty() {
+            return None;
+        }
+        geometry.index_starts.push(geometry.indices.len());
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&geometry.vertices),
+        );
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&geometry.indices),
+        );
+        Some(Self {
+            bindings: Bindings {
+                vertex_buffers: vec![vertex_buffer],
+                index_buffer,
+                images: vec![],
+            },
+            segments: geometry.segments,
+            index_starts: geometry.index_starts,
+        })
+    }
+
+    /// Index span covering the groups whose segment falls inside `range`.
+    fn span(&self, range: &Range<usize>) -> Option<(usize, usize)> {
+        let first = self.segments.partition_point(|&s| s < range.start);
+        let last = self.segments.partition_point(|&s| s < range.end);
+        (first < last).then(|| (self.index_starts[first], self.index_starts[last]))
+    }
+
+    fn buffers(&self) -> [BufferId; 2] {
+        [self.bindings.vertex_buffers[0], self.bindings.index_buffer]
+    }
+}
+
+/// Which batch a draw range applies to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Batch {
+    Extrusions,
+    Travels,
+    Tubes,
+    /// Retraction glyphs
+    Markers,
+    /// Every piece added with [`ToolpathRenderer::push_preview`]
+    Preview,
+}
+
+/// One draw of a segment range from a batch, with a color multiplier.
+pub struct DrawRange {
+    pub batch: Batch,
+    pub range: Range<usize>,
+    pub tint: [f32; 4],
+}
+
+#[repr(C)]
+struct Uniforms {
+    mvp: Mat4,
+    tint: [f32; 4],
+}
+
+const VERTEX_SHADER: &str = r#"#version 100
+attribute vec3 in_position;
+attribute vec4 in_color;
+uniform mat4 mvp;
+uniform vec4 tint;
+varying lowp vec4 color;
+void main() {
+    gl_Position = mvp * vec4(in_position, 1.0);
+    color = in_color / 255.0 * tint;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"#version 100
+varying lowp vec4 color;
+void main() {
+    gl_FragColor = color;
+}
+"#;
+
+/// Static GPU buffers for the whole toolpath.
+///
+/// Geometry is baked once (and again only when colors or visibility change), so a frame
+/// costs a handful of draw calls regardless of how many segments the file has.
+pub struct ToolpathRenderer {
+    line_pipeline: Pipeline,
+    triangle_pipeline: Pipeline,
+    extrusions: Option<GpuBatch>,
+    travels: Option<GpuBatch>,
+    tubes: Option<GpuBatch>,
+    markers: Option<GpuBatch>,
+    preview: Vec<GpuBatch>,
+}
+
+impl ToolpathRenderer {
+    pub fn new() -> Self {
+        let ctx = quad_context();
+        let shader = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: VERTEX_SHADER,
+                    fragment: FRAGMENT_SHADER,
+                },
+                ShaderMeta {
+                    images: vec![],
+                    uniforms: UniformBlockLayout {
+                        uniforms: vec![
+                            UniformDesc::new("mvp", UniformType::Mat4),
+                            UniformDesc::new("tint", UniformType::Float4),
+                        ],
+                    },
+                },
+            )
+            .expect("toolpath shader failed to compile");
+
+        let layout = [BufferLayout::default()];
+        let attributes = [
+            VertexAttribute::new("in_position", VertexFormat::Float3),
+            VertexAttribute::new("in_color", VertexFormat::Byte4),
+        ];
+        let params = |primitive_type| PipelineParams {
+            primitive_type,
+            depth_test: Comparison::LessOrEqual,
+            depth_write: true,
+            color_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            ..Default::default()
+        };
+        let line_pipeline = ctx.new_pipeline(&layout, &attributes, shader, params(PrimitiveType::Lines));
+        let triangle_pipeline =
+            ctx.new_pipeline(&layout, &attributes, shader, params(PrimitiveType::Triangles));
+
+        Self {
+            line_pipeline,
+            triangle_pipeline,
+            extrusions: None,
+            travels: None,
+            tubes: None,
+            markers: None,
+            preview: Vec::new(),
+        }
+    }
+
+    /// Replaces the extrusion and travel line batches.
+    pub fn set_lines(&mut self, extrusions: Geometry, travels: Geometry) {
+        let ctx = quad_context();
+        replace(ctx, &mut self.extrusions, extrusions);
+        replace(ctx, &mut self.travels, travels);
+    }
+
+    /// Replaces the tube batch, or drops it with `None`.
+    pub fn set_tubes(&mut self, tubes: Option<Geometry>) {
+        let ctx = quad_context();
+        match tubes {
+            Some(geometry) => replace(ctx, &mut self.tubes, geometry),
+            None => {
+                if let Some(old) = self.tubes.take() {
+                    delete(ctx, &old);
+                }
+            }
+        }
+    }
+
+    /// Replaces the retraction glyph batch, which is drawn as lines.
+    pub fn set_markers(&mut self, markers: Geometry) {
+        let ctx = quad_context();
+        replace(ctx, &mut self.markers, markers);
+    }
+
+    /// Adds a piece of line geometry to the loading preview.
+    pub fn push_preview(&mut self, geometry: Geometry) {
+        let ctx = quad_context();
+        self.preview.extend(GpuBatch::upload(ctx, geometry));
+    }
+
+    pub fn clear_preview(&mut self) {
+        let ctx = quad_context();
+        for batch in self.preview.drain(..) {
+            delete(ctx, &batch);
+        }
+    }
+
+    /// Draws the given ranges with the current 3D camera, after any pending macroquad drawing.
+    pub fn draw(&self, mvp: Mat4, ranges: &[DrawRange]) {
+        // Safety: called from the render loop on the main thread, like macroquad's own drawing
+        let mut gl = unsafe { get_internal_gl() };
+        gl.flush();
+        let ctx = gl.quad_context;
+        ctx.begin_default_pass(macroquad::miniquad::PassAction::Nothing);
+        for draw in ranges {
+            let (batches, pipeline) = match draw.batch {
+                Batch::Extrusions => (self.extrusions.as_slice(), &self.line_pipeline),
+                Batch::Travels => (self.travels.as_slice(), &self.line_pipeline),
+                Batch::Tubes => (self.tubes.as_slice(), &self.triangle_pipeline),
+                Batch::Markers => (self.markers.as_slice(), &self.line_pipeline),
+                Batch::Preview => (self.preview.as_slice(), &self.line_pipeline),
+            };
+            for batch in batches {
+                let Some((start, end)) = batch.span(&draw.range) else { continue };
+                ctx.apply_pipeline(pipeline);
+                ctx.apply_bindings(&batch.bindings);
+                ctx.apply_uniforms(UniformsSource::table(&Uniforms { mvp, tint: draw.tint }));
+                ctx.draw(start as i32, (end - start) as i32, 1);
+            }
+        }
+        ctx.end_render_pass();
+    }
+}
+
+fn quad_context() -> &'static mut dyn RenderingBackend {
+    // Safety: only called from the main thread while the window is alive
+    unsafe { get_internal_gl() }.quad_context
+}
+
+fn replace(ctx: &mut dyn RenderingBackend, slot: &mut Option<GpuBatch>, geometry: Geometry) {
+    if let Some(old) = slot.take() {
+        delete(ctx, &old);
+    }
+    *slot = GpuBatch::upload(ctx, geometry);
+}
+
+fn delete(ctx: &mut dyn RenderingBackend, batch: &GpuBatch) {
+    for buffer in batch.buffers() {
+        ctx.delete_buffer(buffer);
+    }
+}
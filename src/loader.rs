@@ -0,0 +1,111 @@
+use crate::cli::SceneOptions;
+use crate::{load_scene, Scene, Toolpath};
+use anyhow::{anyhow, Result};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// State of a load shared between the worker thread and the window.
+#[derive(Default)]
+pub struct LoadProgress {
+    /// Fraction of the file read so far, as `f32` bits
+    fraction: AtomicU32,
+    cancelled: AtomicBool,
+    /// Toolpath pieces parsed since the window last took them; `None` when nobody watches
+    preview: Option<Mutex<Vec<Toolpath>>>,
+}
+
+impl LoadProgress {
+    /// Progress that also collects parsed pieces for a preview while loading.
+    pub fn with_preview() -> Self {
+        Self {
+            preview: Some(Mutex::new(Vec::new())),
+            ..Default::default()
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.fraction.load(Ordering::Relaxed))
+    }
+
+    pub fn set_fraction(&self, fraction: f32) {
+        self.fraction.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn wants_preview(&self) -> bool {
+        self.preview.is_some()
+    }
+
+    /// Hands a freshly parsed piece of the toolpath to the preview.
+    pub fn send_preview(&self, part: Toolpath) {
+        if let Some(preview) = &self.preview {
+            preview.lock().unwrap().push(part);
+        }
+    }
+
+    /// Pieces sent since the last call, in file order.
+    pub fn take_preview(&self) -> Vec<Toolpath> {
+        match &self.preview {
+            Some(preview) => mem::take(&mut *preview.lock().unwrap()),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A file being loaded on a worker thread, so the window stays responsive.
+pub struct Loader {
+    pub filename: String,
+    progress: Arc<LoadProgress>,
+    result: Receiver<Result<Scene>>,
+}
+
+impl Loader {
+    /// Starts loading `filename`; `preview` collects parsed pieces for showing while it loads.
+    pub fn start(filename: String, options: SceneOptions, preview: bool) -> Self {
+        let progress = Arc::new(if preview {
+            LoadProgress::with_preview()
+        } else {
+            LoadProgress::default()
+        });
+        let (sender, result) = mpsc::channel();
+        let worker_progress = Arc::clone(&progress);
+        let worker_filename = filename.clone();
+        thread::spawn(move || {
+            // The window may have gone away; nothing to do with the result then
+            let _ = sender.send(load_scene(&worker_filename, options, &worker_progress));
+        });
+        Self {
+            filename,
+            progress,
+            result,
+        }
+    }
+
+    pub fn progress(&self) -> &LoadProgress {
+        &self.progress
+    }
+
+    /// Asks the worker to stop; it finishes with a "cancelled" error shortly after.
+    pub fn cancel(&self) {
+        self.progress.cancel();
+    }
+
+    /// The loaded scene, or why it failed, once the worker is done.
+    pub fn poll(&self) -> Option<Result<Scene>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("Loader thread stopped unexpectedly"))),
+        }
+    }
+}
@@ -1,10 +1,60 @@
-use anyhow::{Context, Result};
+mod analyze;
+mod arc;
+mod cli;
+mod color_mode;
+mod feature;
+mod gpu;
+mod loader;
+mod metadata;
+mod pick;
+mod planner;
+mod profile;
+mod raster;
+mod reader;
+mod render;
+mod retract;
+mod settings_panel;
+mod source;
+mod source_panel;
+mod stats;
+mod thumbnail;
+mod tool;
+mod travel;
+mod tube;
+mod watcher;
+
+use anyhow::Result;
+use arc::{tessellate_arc, ArcCenter, ArcPlane};
+use cli::{Command, Options, SceneOptions};
+use color_mode::{gradient, ColorMode, ColorScale, Coloring};
+use feature::FeatureType;
+use gpu::{Batch, DrawRange, Geometry, GpuVertex, ToolpathRenderer};
+use loader::{LoadProgress, Loader};
+use metadata::{Metadata, MetadataCollector};
+use planner::{format_duration, parse_duration, Planner, PlannerMove};
+use profile::Region;
+use reader::Line;
+use retract::{RetractEvent, RetractKind};
+use settings_panel::SettingsPanel;
+use source::SourceIndex;
+use source_panel::SourcePanel;
+use stats::{format_length, FilamentStats, Usage, FILAMENT_TOLERANCE};
+use thumbnail::{Thumbnail, ThumbnailCollector};
+use tool::{ToolChanges, MAX_TOOLS};
+use travel::{TravelKind, TravelStats};
+use tube::{build_tubes, Bead};
+use watcher::FileWatcher;
 use gcode::Mnemonic;
+use macroquad::camera::Camera as _;
 use macroquad::prelude::*;
 use std::env;
-use std::fs;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct Vec3D {
     x: f32,
     y: f32,
@@ -19,16 +69,221 @@ impl Vec3D {
     fn zero() -> Self {
         Self::new(0.0, 0.0, 0.0)
     }
+
+    fn distance(self, other: Vec3D) -> f32 {
+        ((other.x - self.x).powi(2) + (other.y - self.y).powi(2) + (other.z - self.z).powi(2)).sqrt()
+    }
 }
 
-#[derive(Clone, Debug)]
+/// Per-move attributes, packed; positions live in [`Toolpath::points`].
+#[derive(Clone, Copy, Debug)]
 struct LineSegment {
-    start: Vec3D,
-    end: Vec3D,
-    is_extrusion: bool,
-    layer_z: f32,
+    /// Index of the start point in the shared point list; the end point is the next one
+    start: u32,
+    /// Slicer layer number; 0 marks everything before the first layer change, which
+    /// [`build_layers`] puts in the first layer
+    layer: u32,
+    /// 1-based line in the file of the command that made the move; arcs share theirs across chords
+    line: u32,
+    /// Commanded feedrate in mm/s
+    feedrate: f32,
+    /// Estimated time in seconds, including any E-only moves and dwells that follow it
+    duration: f32,
+    /// Filament pushed during the move, in mm of E
+    e: f32,
+    /// Line width in µm from `; LINE_WIDTH:` / `;WIDTH:` annotations, 0 when the slicer didn't say
+    width_um: u16,
+    /// Layer height in µm from `; LAYER_HEIGHT:` / `;HEIGHT:` annotations, 0 when the slicer didn't say
+    height_um: u16,
+    /// Nozzle target temperature in °C
+    temperature: u16,
+    /// Part cooling fan, 0-100%
+    fan: u8,
+    /// Active tool (`T` number), which picks the filament
+    tool: u8,
+    /// Feature type in the low bits, plus [`EXTRUSION_FLAG`] and [`WIPE_FLAG`]
+    flags: u8,
+    /// What a travel is for, from [`travel::classify`]; unused for extrusions
+    travel: TravelKind,
+}
+
+/// Set in [`LineSegment::flags`] for moves that push filament.
+const EXTRUSION_FLAG: u8 = 0x80;
+/// Set in [`LineSegment::flags`] for moves between `; WIPE_START` and `; WIPE_END`.
+const WIPE_FLAG: u8 = 0x40;
+
+impl LineSegment {
+    fn is_extrusion(&self) -> bool {
+        self.flags & EXTRUSION_FLAG != 0
+    }
+
+    fn is_wipe(&self) -> bool {
+        self.flags & WIPE_FLAG != 0
+    }
+
+    fn feature(&self) -> FeatureType {
+        FeatureType::ALL[(self.flags & !(EXTRUSION_FLAG | WIPE_FLAG)) as usize]
+    }
+
+    /// Bead (width, height) in mm, preferring the slicer's annotations.
+    ///
+    /// Without a width annotation the width is solved from the extruded volume over the
+    /// move's `length`, assuming the usual rounded-rectangle cross-section A = (w - h) * h + π (h/2)².
+    fn bead_size(&self, length: f32, layer_height: f32, filament_area: f32) -> (f32, f32) {
+        let height = if self.height_um > 0 { self.height_um as f32 / 1000.0 } else { layer_height };
+        let width = if self.width_um > 0 {
+            self.width_um as f32 / 1000.0
+        } else {
+            let area = self.e * filament_area / length;
+            area / height + height * (1.0 - std::f32::consts::PI / 4.0)
+        };
+        (width, height)
+    }
+}
+
+/// Converts mm to the µm stored in [`LineSegment`], saturating at ~65 mm.
+fn to_microns(mm: f32) -> u16 {
+    (mm * 1000.0).round().clamp(0.0, u16::MAX as f32) as u16
+}
+
+/// All moves of a file, in file order.
+///
+/// Consecutive moves share their common point, so a continuous toolpath stores one point
+/// per segment instead of two.
+#[derive(Default)]
+struct Toolpath {
+    points: Vec<Vec3D>,
+    segments: Vec<LineSegment>,
+}
+
+impl Toolpath {
+    fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    fn start(&self, index: usize) -> Vec3D {
+        self.points[self.segments[index].start as usize]
+    }
+
+    fn end(&self, index: usize) -> Vec3D {
+        self.points[self.segments[index].start as usize + 1]
+    }
+
+    fn length(&self, index: usize) -> f32 {
+        self.start(index).distance(self.end(index))
+    }
+
+    /// Appends a move from `start` to `end`, reusing the last point when it is `start`.
+    fn push(&mut self, start: Vec3D, end: Vec3D, mut segment: LineSegment) {
+        if self.points.last() != Some(&start) {
+            self.points.push(start);
+        }
+        segment.start = (self.points.len() - 1) as u32;
+        self.points.push(end);
+        self.segments.push(segment);
+    }
+
+    /// Segments made by the command on 1-based `line`: several for an arc, none for anything but a move.
+    fn line_segments(&self, line: u32) -> Range<usize> {
+        let start = self.segments.partition_point(|s| s.line < line);
+        let end = self.segments.partition_point(|s| s.line <= line);
+        start..end
+    }
+
+    /// Copy of the segments in `range`, with only the points they use.
+    fn slice(&self, range: Range<usize>) -> Toolpath {
+        if range.is_empty() {
+            return Toolpath::default();
+        }
+        let first = self.segments[range.start].start;
+        let last = self.segments[range.end - 1].start + 1;
+        Toolpath {
+            points: self.points[first as usize..=last as usize].to_vec(),
+            segments: self.segments[range]
+                .iter()
+                .map(|seg| LineSegment { start: seg.start - first, ..*seg })
+                .collect(),
+        }
+    }
+}
+
+/// Everything `parse_gcode` extracts from a file.
+struct ParsedGcode {
+    toolpath: Toolpath,
+    /// Planner estimate for the whole file, in seconds
+    print_time: f32,
+    /// The slicer's own estimate from the file header, in seconds
+    slicer_time: Option<f32>,
+    metadata: Metadata,
+    tool_changes: ToolChanges,
+    retractions: Vec<RetractEvent>,
+    /// Segment counts at the slicer's section markers
+    markers: SectionMarkers,
+    thumbnails: Vec<Thumbnail>,
+}
+
+/// Where the slicer marked the machine start/end G-code, as segment indices.
+#[derive(Default)]
+struct SectionMarkers {
+    /// `EXECUTABLE_BLOCK_START`: where the start G-code begins
+    executable_start: Option<usize>,
+    /// `MACHINE_START_GCODE_END`: first segment of the print itself
+    start_gcode_end: Option<usize>,
+    /// `MACHINE_END_GCODE_START`: first segment of the end G-code
+    end_gcode_start: Option<usize>,
+}
+
+/// A contiguous run of segments printed at one layer height.
+struct Layer {
+    number: u32,
+    z: f32,
+    height: f32,
+    range: Range<usize>,
+    /// Estimated time to print the layer, in seconds
+    time: f32,
 }
 
+/// A loaded file, ready to display.
+struct Scene {
+    filename: String,
+    toolpath: Toolpath,
+    layers: Vec<Layer>,
+    /// Segments between the machine start and end G-code
+    print_range: Range<usize>,
+    /// Extent of every extrusion in the file
+    bounds: Bounds,
+    /// Extent of the extrusions in `print_range`, which the camera frames when filtering
+    print_bounds: Bounds,
+    /// Planner estimate in seconds
+    print_time: f32,
+    /// The slicer's estimate in seconds
+    slicer_time: Option<f32>,
+    /// Planner and slicer time estimates for the HUD
+    time_text: String,
+    /// In mm, 1.75 unless the file says otherwise
+    filament_diameter: f32,
+    stats: FilamentStats,
+    legend_features: Vec<FeatureType>,
+    /// Tools that extrude something, in number order
+    legend_tools: Vec<u8>,
+    tool_changes: ToolChanges,
+    tool_colors: [(u8, u8, u8); MAX_TOOLS],
+    retractions: Vec<RetractEvent>,
+    travel_stats: TravelStats,
+    /// Travel kinds that occur, in legend order
+    legend_travels: Vec<TravelKind>,
+    /// Line offsets for reading the G-code text back
+    source: SourceIndex,
+    /// Largest embedded PNG thumbnail, decoded for the HUD
+    thumbnail: Option<Image>,
+    metadata: Metadata,
+}
+
+#[derive(Clone)]
 struct Bounds {
     min: Vec3D,
     max: Vec3D,
@@ -75,6 +330,11 @@ struct Camera {
 }
 
 impl Camera {
+    /// Vertical field of view, as passed to macroquad (which takes radians)
+    const FOVY: f32 = 45.0;
+    /// Frames the normalized scene, which spans 2 units along its longest side
+    const DEFAULT_DISTANCE: f32 = 3.0;
+
     fn new(distance: f32) -> Self {
         Self {
             distance,
@@ -91,6 +351,26 @@ impl Camera {
         self.target + vec3(x, y, z)
     }
 
+    fn camera_3d(&self) -> Camera3D {
+        Camera3D {
+            position: self.position(),
+            target: self.target,
+            up: vec3(0.0, 1.0, 0.0),
+            fovy: Self::FOVY,
+            projection: Projection::Perspective,
+            ..Default::default()
+        }
+    }
+
+    /// Projection × view for a viewport of the given aspect ratio, as macroquad builds it.
+    ///
+    /// `Camera3D::matrix` reads the window size, so rendering without a window needs this.
+    fn matrix(&self, aspect: f32) -> Mat4 {
+        let cam = self.camera_3d();
+        Mat4::perspective_rh_gl(cam.fovy, aspect, cam.z_near, cam.z_far)
+            * Mat4::look_at_rh(cam.position, cam.target, cam.up)
+    }
+
     fn reset(&mut self, distance: f32) {
         self.distance = distance;
         self.yaw = 45.0_f32.to_radians();
@@ -98,133 +378,1162 @@ impl Camera {
     }
 }
 
-fn parse_gcode(filename: &str) -> Result<Vec<LineSegment>> {
-    let content = fs::read_to_string(filename)
-        .context(format!("Failed to read file: {}", filename))?;
+/// How G90/G91 interact with the extruder axis, which differs between firmwares.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FirmwareFlavor {
+    /// Marlin: G90 and G91 set E absolute or relative along with the other axes.
+    Marlin,
+    /// Klipper: G91 also makes E relative, G90 falls back to the M82/M83 mode.
+    Klipper,
+    /// RepRapFirmware: E positioning is controlled by M82/M83 only.
+    RepRap,
+}
+
+impl FirmwareFlavor {
+    /// Maps a slicer `gcode_flavor` setting onto the firmware behaviour we model.
+    fn from_setting(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "reprapfirmware" | "reprap" | "rrf" => FirmwareFlavor::RepRap,
+            "klipper" => FirmwareFlavor::Klipper,
+            _ => FirmwareFlavor::Marlin,
+        }
+    }
+
+    /// Flavor from a `gcode_flavor` setting in the first or last [`FLAVOR_SCAN_BYTES`] of the file.
+    ///
+    /// PrusaSlicer, SuperSlicer and Orca write their config block after the moves, so the
+    /// setting has to be found before parsing for it to apply to the whole file.
+    fn scan(filename: &str) -> Option<Self> {
+        let mut file = File::open(filename).ok()?;
+        let len = file.metadata().ok()?.len();
+        let mut head = Vec::new();
+        file.by_ref().take(FLAVOR_SCAN_BYTES).read_to_end(&mut head).ok()?;
+        let mut tail = Vec::new();
+        if len > FLAVOR_SCAN_BYTES {
+            file.seek(SeekFrom::Start(len.saturating_sub(FLAVOR_SCAN_BYTES).max(FLAVOR_SCAN_BYTES))).ok()?;
+            file.read_to_end(&mut tail).ok()?;
+        }
+        [head, tail].iter().find_map(|bytes| {
+            String::from_utf8_lossy(bytes).lines().find_map(|line| {
+                let setting = line.trim().strip_prefix(';')?.trim();
+                setting.strip_prefix("gcode_flavor =").map(Self::from_setting)
+            })
+        })
+    }
+}
+
+/// How much of each end of a file [`FirmwareFlavor::scan`] reads.
+const FLAVOR_SCAN_BYTES: u64 = 512 << 10;
 
-    let mut segments = Vec::new();
+/// Parses a G-code file, streaming it so memory only grows with the compact [`Toolpath`].
+///
+/// Reports the fraction read to `progress`, sends pieces of the toolpath to its preview
+/// if it wants one, and gives up with an error once it is cancelled.
+fn parse_gcode(filename: &str, progress: &LoadProgress) -> Result<ParsedGcode> {
+    let mut toolpath = Toolpath::default();
+    let mut preview_sent = 0;
+    // Machine position; G92 shifts the logical coordinates by `work_offset`
     let mut current_pos = Vec3D::zero();
+    let mut work_offset = Vec3D::zero();
     let mut e_pos = 0.0_f32;
     let mut absolute_mode = true;
+    // Extruder mode: M82 = absolute, M83 = relative; Marlin's G90/G91 set it as well
+    let mut absolute_e = true;
+    let flavor = FirmwareFlavor::scan(filename).unwrap_or(FirmwareFlavor::Marlin);
+    let mut arc_plane = ArcPlane::XY;
+    let mut feature = FeatureType::Unknown;
+    // Layer numbers from slicer markers, with a Z-change fallback for files without them
+    let mut layer = 0_u32;
+    let mut saw_layer_marker = false;
+    // Feedrate is modal and shared by G0/G1; F is in mm/min, stored as mm/s
+    let mut feedrate = 25.0_f32;
+    let mut planner = Planner::new();
+    let mut print_time = 0.0_f32;
+    let mut slicer_time = None;
+    // Segment owning each block still in the planner: the last one pushed before it
+    let mut move_owners = VecDeque::new();
+    let mut width = 0.0_f32;
+    let mut height = 0.0_f32;
+    let mut fan = 0.0_f32;
+    let mut temperature = 0.0_f32;
+    let mut metadata = MetadataCollector::default();
+    let mut before_codes = true;
+    let mut tool_changes = ToolChanges::default();
+    let mut current_tool = 0_u8;
+    let mut extruded = false;
+    // Inside a `; FLUSH_START` / `; FLUSH_END` block of the filament change G-code
+    let mut flushing = false;
+    let mut wiping = false;
+    let mut retractions = Vec::new();
+    // G10 length from M207, unknown until the file sets it
+    let mut firmware_retract_length = 0.0_f32;
+    let mut markers = SectionMarkers::default();
+    let mut thumbnails = ThumbnailCollector::default();
 
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with(';') {
-            continue;
+    // Planner durations come back in move order; each is added to its owner's time
+    let mut plan_move = |toolpath: &mut Toolpath, planner: &mut Planner, mv: PlannerMove| {
+        move_owners.push_back(toolpath.len().checked_sub(1));
+        if let Some(duration) = planner.push(mv) {
+            print_time += duration;
+            if let Some(index) = move_owners.pop_front().flatten() {
+                toolpath.segments[index].duration += duration;
+            }
         }
+    };
 
-        for parsed_line in gcode::parse(trimmed) {
-            for gcode in parsed_line.gcodes() {
+    reader::read_lines(filename, |line_number, line| match line {
+        Line::Comment(comment) => {
+            let trimmed = comment.trim();
+            if thumbnails.push_comment(trimmed) {
+                // Base64 image data, nothing else to find in it
+                return;
+            }
+            metadata.push_comment(trimmed, before_codes);
+            if trimmed == "EXECUTABLE_BLOCK_START" {
+                markers.executable_start.get_or_insert(toolpath.len());
+            } else if trimmed == "MACHINE_START_GCODE_END" {
+                markers.start_gcode_end.get_or_insert(toolpath.len());
+            } else if trimmed == "MACHINE_END_GCODE_START" {
+                markers.end_gcode_start = Some(toolpath.len());
+            } else if trimmed == "FLUSH_START" {
+                flushing = true;
+            } else if trimmed == "FLUSH_END" {
+                flushing = false;
+            } else if trimmed == "WIPE_START" {
+                wiping = true;
+            } else if trimmed == "WIPE_END" {
+                wiping = false;
+            } else if let Some(annotated) = FeatureType::from_comment(comment) {
+                feature = annotated;
+            } else if is_layer_change_marker(comment) {
+                layer += 1;
+                saw_layer_marker = true;
+            } else if let Some(time) = parse_slicer_time(comment) {
+                slicer_time = Some(time);
+            } else if let Some(value) = comment_value(comment, &["LINE_WIDTH:", "WIDTH:"]) {
+                width = value;
+            } else if let Some(value) = comment_value(comment, &["LAYER_HEIGHT:", "HEIGHT:"]) {
+                height = value;
+            }
+        }
+        Line::Codes(codes) => {
+            before_codes = false;
+            for gcode in &codes {
                 match gcode.mnemonic() {
                     Mnemonic::General => {
                         let major = gcode.major_number();
-                        if major == 0 || major == 1 {
-                            // G0 (rapid) or G1 (linear move)
+                        if major <= 3 {
+                            // G0 (rapid), G1 (linear move), G2/G3 (clockwise/counter-clockwise arc)
                             let mut new_pos = current_pos;
                             let mut new_e = e_pos;
+                            let mut arc_offset = Vec3D::zero();
+                            let mut arc_radius = None;
+                            let relative_e = !absolute_e
+                                || (flavor == FirmwareFlavor::Klipper && !absolute_mode);
 
+                            // Absolute targets are logical coordinates; shift them by
+                            // the G92 offset to get machine coordinates
                             for arg in gcode.arguments() {
                                 match arg.letter {
-                                    'X' => new_pos.x = arg.value as f32,
-                                    'Y' => new_pos.y = arg.value as f32,
-                                    'Z' => new_pos.z = arg.value as f32,
-                                    'E' => new_e = arg.value as f32,
+                                    'X' if absolute_mode => new_pos.x = arg.value + work_offset.x,
+                                    'Y' if absolute_mode => new_pos.y = arg.value + work_offset.y,
+                                    'Z' if absolute_mode => new_pos.z = arg.value + work_offset.z,
+                                    'X' => new_pos.x += arg.value,
+                                    'Y' => new_pos.y += arg.value,
+                                    'Z' => new_pos.z += arg.value,
+                                    'E' if relative_e => new_e += arg.value,
+                                    'E' => new_e = arg.value,
+                                    'I' => arc_offset.x = arg.value,
+                                    'J' => arc_offset.y = arg.value,
+                                    'K' => arc_offset.z = arg.value,
+                                    'R' => arc_radius = Some(arg.value),
+                                    'F' => feedrate = arg.value / 60.0,
                                     _ => {}
                                 }
                             }
 
-                            if !absolute_mode {
-                                new_pos.x += current_pos.x;
-                                new_pos.y += current_pos.y;
-                                new_pos.z += current_pos.z;
-                                new_e += e_pos;
-                            }
-
                             let is_extrusion = new_e > e_pos;
 
-                            if new_pos.x != current_pos.x || new_pos.y != current_pos.y || new_pos.z != current_pos.z {
-                                segments.push(LineSegment {
-                                    start: current_pos,
-                                    end: new_pos,
-                                    is_extrusion,
-                                    layer_z: new_pos.z,
+                            let points = if major >= 2 {
+                                let center = match arc_radius {
+                                    Some(radius) => ArcCenter::Radius(radius),
+                                    None => ArcCenter::Offset(arc_offset),
+                                };
+                                tessellate_arc(current_pos, new_pos, center, major == 2, arc_plane)
+                                    .unwrap_or_else(|| vec![new_pos])
+                            } else {
+                                vec![new_pos]
+                            };
+
+                            // Arc chords share the E move in proportion to their length
+                            let e_delta = new_e - e_pos;
+                            let mut path_length = 0.0;
+                            let mut prev = current_pos;
+                            for point in &points {
+                                path_length += prev.distance(*point);
+                                prev = *point;
+                            }
+                            if flushing && path_length == 0.0 && e_delta > 0.0 {
+                                tool_changes.flush_e[current_tool as usize] += e_delta;
+                            } else if e_delta < 0.0 || (e_delta > 0.0 && path_length == 0.0) {
+                                let kind = if e_delta < 0.0 { RetractKind::Retract } else { RetractKind::Unretract };
+                                retract::record(&mut retractions, &toolpath, RetractEvent {
+                                    kind,
+                                    position: current_pos,
+                                    length: e_delta.abs(),
+                                    segment: toolpath.len(),
                                 });
                             }
+                            extruded |= is_extrusion && path_length > 0.0;
+
+                            let mut start = current_pos;
+                            for end in points {
+                                let length = start.distance(end);
+                                let e = if path_length > 0.0 {
+                                    e_delta * length / path_length
+                                } else {
+                                    e_delta
+                                };
+                                if length > 0.0 {
+                                    let extrusion_flag = if is_extrusion { EXTRUSION_FLAG } else { 0 };
+                                    let wipe_flag = if wiping { WIPE_FLAG } else { 0 };
+                                    toolpath.push(start, end, LineSegment {
+                                        start: 0,
+                                        layer,
+                                        line: line_number,
+                                        feedrate,
+                                        duration: 0.0,
+                                        e,
+                                        width_um: to_microns(width),
+                                        height_um: to_microns(height),
+                                        temperature: temperature.round().clamp(0.0, u16::MAX as f32) as u16,
+                                        fan: fan.round() as u8,
+                                        tool: current_tool,
+                                        flags: feature as u8 | extrusion_flag | wipe_flag,
+                                        travel: if major == 0 { TravelKind::Rapid } else { TravelKind::Travel },
+                                    });
+                                }
+                                if length > 0.0 || e != 0.0 {
+                                    let mv = PlannerMove {
+                                        delta: [end.x - start.x, end.y - start.y, end.z - start.z, e],
+                                        feedrate,
+                                        dwell: 0.0,
+                                    };
+                                    plan_move(&mut toolpath, &mut planner, mv);
+                                }
+                                start = end;
+                            }
 
                             current_pos = new_pos;
                             e_pos = new_e;
-                        } else if major == 90 {
-                            absolute_mode = true;
-                        } else if major == 91 {
-                            absolute_mode = false;
+                        } else if major == 4 {
+                            // G4 dwell: P in milliseconds, S in seconds
+                            let dwell = gcode.arguments().iter().fold(0.0, |acc, arg| match arg.letter {
+                                'P' => acc + arg.value / 1000.0,
+                                'S' => acc + arg.value,
+                                _ => acc,
+                            });
+                            let mv = PlannerMove {
+                                delta: [0.0; 4],
+                                feedrate,
+                                dwell,
+                            };
+                            plan_move(&mut toolpath, &mut planner, mv);
+                        } else if (major == 10 || major == 11)
+                            && gcode.arguments().iter().all(|arg| arg.letter == 'S')
+                        {
+                            // Firmware retraction; G10 with L/P sets work offsets instead
+                            let kind = if major == 10 { RetractKind::FirmwareRetract } else { RetractKind::FirmwareUnretract };
+                            retract::record(&mut retractions, &toolpath, RetractEvent {
+                                kind,
+                                position: current_pos,
+                                length: firmware_retract_length,
+                                segment: toolpath.len(),
+                            });
+                        } else if major == 17 {
+                            arc_plane = ArcPlane::XY;
+                        } else if major == 18 {
+                            arc_plane = ArcPlane::ZX;
+                        } else if major == 19 {
+                            arc_plane = ArcPlane::YZ;
+                        } else if major == 90 || major == 91 {
+                            absolute_mode = major == 90;
+                            if flavor == FirmwareFlavor::Marlin {
+                                absolute_e = absolute_mode;
+                            }
+                        } else if major == 92 {
+                            if gcode.minor_number() == 1 {
+                                // G92.1: drop the offset, logical = machine coordinates
+                                work_offset = Vec3D::zero();
+                            } else if gcode.arguments().is_empty() {
+                                // Bare G92 zeroes every axis at the current position
+                                work_offset = current_pos;
+                                e_pos = 0.0;
+                            } else {
+                                // G92 sets the logical position without moving
+                                for arg in gcode.arguments() {
+                                    match arg.letter {
+                                        'X' => work_offset.x = current_pos.x - arg.value,
+                                        'Y' => work_offset.y = current_pos.y - arg.value,
+                                        'Z' => work_offset.z = current_pos.z - arg.value,
+                                        'E' => e_pos = arg.value,
+                                        _ => {}
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    Mnemonic::Miscellaneous => {
+                        let major = gcode.major_number();
+                        if major == 82 {
+                            absolute_e = true;
+                        } else if major == 83 {
+                            absolute_e = false;
+                        } else if major == 104 || major == 109 {
+                            if let Some(arg) = gcode.arguments().iter().find(|arg| arg.letter == 'S') {
+                                temperature = arg.value;
+                            }
+                        } else if major == 106 {
+                            // M106 S0-255; no S means full speed
+                            let speed = gcode
+                                .arguments()
+                                .iter()
+                                .find(|arg| arg.letter == 'S')
+                                .map_or(255.0, |arg| arg.value);
+                            fan = (speed / 255.0 * 100.0).clamp(0.0, 100.0);
+                        } else if major == 107 {
+                            fan = 0.0;
+                        } else if major == 207 {
+                            if let Some(arg) = gcode.arguments().iter().find(|arg| arg.letter == 'S') {
+                                firmware_retract_length = arg.value;
+                            }
+                        } else if (201..=205).contains(&major) {
+                            planner.limits.apply(major, gcode.arguments().iter().map(|arg| (arg.letter, arg.value)));
+                        }
+                    }
+                    Mnemonic::ToolChange => {
+                        let next = gcode.major_number() as usize;
+                        if next < MAX_TOOLS && next != current_tool as usize {
+                            if extruded {
+                                tool_changes.counts[next] += 1;
+                            }
+                            current_tool = next as u8;
                         }
                     }
                     _ => {}
                 }
             }
+
+            if progress.wants_preview() && toolpath.len() >= preview_sent + PREVIEW_SEGMENTS {
+                progress.send_preview(toolpath.slice(preview_sent..toolpath.len()));
+                preview_sent = toolpath.len();
+            }
+        }
+    }, |fraction| {
+        progress.set_fraction(fraction);
+        !progress.is_cancelled()
+    })?;
+
+    for (duration, owner) in planner.finish().into_iter().zip(move_owners) {
+        print_time += duration;
+        if let Some(index) = owner {
+            toolpath.segments[index].duration += duration;
+        }
+    }
+
+    if !saw_layer_marker {
+        // No slicer markers: a new layer starts whenever an extrusion rises above the last one
+        let mut z_layer = 0;
+        let mut last_extrusion_z = f32::NEG_INFINITY;
+        for index in 0..toolpath.len() {
+            let z = toolpath.end(index).z;
+            let seg = &mut toolpath.segments[index];
+            if seg.is_extrusion() && z > last_extrusion_z + LAYER_Z_EPSILON {
+                z_layer += 1;
+                last_extrusion_z = z;
+            }
+            seg.layer = z_layer;
         }
     }
 
-    Ok(segments)
+    Ok(ParsedGcode {
+        toolpath,
+        print_time,
+        slicer_time,
+        metadata: metadata.finish(),
+        tool_changes,
+        retractions,
+        markers,
+        thumbnails: thumbnails.finish(),
+    })
 }
 
-fn filter_priming_lines(segments: &[LineSegment]) -> Vec<LineSegment> {
-    if segments.is_empty() {
-        return Vec::new();
+/// Numeric value of a `KEY: value` style comment, taking the first of comma-separated values.
+fn comment_value(comment: &str, keys: &[&str]) -> Option<f32> {
+    let comment = comment.trim();
+    let value = keys.iter().find_map(|key| comment.strip_prefix(key))?;
+    value.split(',').next()?.trim().parse().ok()
+}
+
+/// Reads the slicer's time estimate: `estimated printing time (normal mode) = 1h 2m 3s`
+/// (BambuStudio, Orca, PrusaSlicer) or Cura's `TIME:<seconds>`.
+fn parse_slicer_time(comment: &str) -> Option<f32> {
+    let comment = comment.trim();
+    if let Some(seconds) = comment.strip_prefix("TIME:") {
+        return seconds.trim().parse().ok();
     }
+    if comment.starts_with("estimated printing time") && !comment.contains("silent") {
+        return parse_duration(comment.split('=').nth(1)?.trim());
+    }
+    None
+}
 
-    // Strategy: Find where the actual print starts and ends by looking for
-    // clusters of extrusion moves away from edges
-    // This skips priming, homing, and positioning moves
-    
-    let mut start_index = None;
-    let mut end_index = None;
-    
-    // Find first cluster of extrusion moves away from edges
-    for (i, window) in segments.windows(5).enumerate() {
-        let extrusion_count = window.iter().filter(|s| s.is_extrusion).count();
-        
-        if extrusion_count >= 3 {
-            let away_from_edges = window.iter().all(|s| {
-                let at_edge = s.start.x < 10.0 || s.end.x < 10.0 ||
-                             s.start.y < 20.0 || s.end.y < 20.0;
-                let long_move = (s.end.x - s.start.x).abs() > 100.0 ||
-                               (s.end.y - s.start.y).abs() > 100.0;
-                !at_edge && !long_move
-            });
-            
-            if away_from_edges {
-                start_index = Some(i);
-                break;
-            }
-        }
+/// Segments collected before a piece is sent to the loading preview.
+const PREVIEW_SEGMENTS: usize = 20_000;
+
+/// Minimum Z rise between extrusions that counts as a new layer when the file has no markers.
+const LAYER_Z_EPSILON: f32 = 0.001;
+
+/// Recognises layer-change comments: BambuStudio `CHANGE_LAYER`, PrusaSlicer/Orca
+/// `LAYER_CHANGE` and Cura `LAYER:<n>`.
+fn is_layer_change_marker(comment: &str) -> bool {
+    let comment = comment.trim();
+    comment == "CHANGE_LAYER" || comment == "LAYER_CHANGE" || comment.starts_with("LAYER:")
+}
+
+/// Groups consecutive segments by layer number.
+///
+/// Layer Z comes from the first extrusion in the layer so z-hops don't skew it. Whatever comes
+/// before the first layer change (start G-code, purge lines) joins the first layer without
+/// setting its Z, and that layer's height is the slicer's `first_layer_height` or else its Z.
+fn build_layers(toolpath: &Toolpath, first_layer_height: Option<f32>) -> Vec<Layer> {
+    let segments = &toolpath.segments;
+    let mut layers: Vec<Layer> = Vec::new();
+    let lead_in = segments.iter().take_while(|s| s.layer == 0).count();
+    let mut start = if lead_in < segments.len() { lead_in } else { 0 };
+    while start < segments.len() {
+        let number = segments[start].layer;
+        let len = segments[start..]
+            .iter()
+            .take_while(|s| s.layer == number)
+            .count();
+        let run = start..start + len;
+        let z = run
+            .clone()
+            .find(|&i| segments[i].is_extrusion())
+            .map(|i| toolpath.end(i).z)
+            .unwrap_or_else(|| run.clone().map(|i| toolpath.end(i).z).fold(f32::INFINITY, f32::min));
+        let (range, height) = match layers.last() {
+            Some(prev) => (run, z - prev.z),
+            None => (0..run.end, first_layer_height.filter(|&h| h > 0.0).unwrap_or(z)),
+        };
+        layers.push(Layer {
+            number,
+            z,
+            height,
+            time: segments[range.clone()].iter().map(|s| s.duration).sum(),
+            range,
+        });
+        start += len;
     }
-    
-    // Find last extrusion (end of actual print)
-    end_index = segments.iter()
-        .rposition(|s| s.is_extrusion)
-        .map(|i| i + 1); // +1 to include this segment
-    
-    let start = start_index.unwrap_or(0);
-    let end = end_index.unwrap_or(segments.len());
-    
-    segments[start..end].to_vec()
+    layers
 }
 
-fn compute_bounds(segments: &[LineSegment]) -> Bounds {
+/// Segments of the print itself, between the machine start and end G-code.
+///
+/// The slicer's section markers decide when present. Without them the start sequence
+/// runs up to the first extrusion outside the printer's purge region (or simply the first
+/// extrusion when the region isn't known), and the end sequence is everything after the
+/// last extrusion.
+fn find_print_range(toolpath: &Toolpath, markers: &SectionMarkers, purge_region: Option<Region>) -> Range<usize> {
+    let segments = &toolpath.segments;
+    let in_purge_region = |index: usize| {
+        purge_region.is_some_and(|region| {
+            let (start, end) = (toolpath.start(index), toolpath.end(index));
+            region.contains(start.x, start.y) && region.contains(end.x, end.y)
+        })
+    };
+
+    let start = markers.start_gcode_end.unwrap_or_else(|| {
+        let from = markers.executable_start.unwrap_or(0);
+        (from..segments.len())
+            .find(|&i| segments[i].is_extrusion() && !in_purge_region(i))
+            .unwrap_or(from)
+    });
+    let end = markers.end_gcode_start.unwrap_or_else(|| {
+        segments
+            .iter()
+            .rposition(|s| s.is_extrusion())
+            .map_or(segments.len(), |i| i + 1) // +1 to include this segment
+    });
+    start..end.max(start)
+}
+
+fn compute_bounds(toolpath: &Toolpath, range: Range<usize>) -> Bounds {
     let mut bounds = Bounds::new();
     
     // Only compute bounds from extrusion moves to ignore travel/homing
-    for seg in segments {
-        if seg.is_extrusion {
-            bounds.expand(seg.start);
-            bounds.expand(seg.end);
+    for index in range {
+        if toolpath.segments[index].is_extrusion() {
+            bounds.expand(toolpath.start(index));
+            bounds.expand(toolpath.end(index));
         }
     }
     
     bounds
 }
 
+const BACKGROUND: Color = Color::from_rgba(20, 20, 30, 255);
+
+const LEGEND_X: f32 = 10.0;
+const LEGEND_TOP: f32 = 50.0;
+const LEGEND_ROW_HEIGHT: f32 = 20.0;
+const LEGEND_WIDTH: f32 = 190.0;
+
+const NUMBER_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// Which moves are shown: extrusions by feature type and by tool, travels by kind.
+#[derive(Clone, Copy)]
+struct Visibility {
+    features: [bool; FeatureType::COUNT],
+    tools: [bool; MAX_TOOLS],
+    travels: [bool; TravelKind::COUNT],
+}
+
+impl Visibility {
+    fn all() -> Self {
+        Self {
+            features: [true; FeatureType::COUNT],
+            tools: [true; MAX_TOOLS],
+            travels: [true; TravelKind::COUNT],
+        }
+    }
+
+    fn shows(&self, seg: &LineSegment) -> bool {
+        if seg.is_extrusion() {
+            self.features[seg.feature() as usize] && self.tools[seg.tool as usize]
+        } else {
+            self.travels[seg.travel as usize]
+        }
+    }
+}
+
+/// Feature types that actually occur in the extrusion moves, in legend order.
+fn present_features(segments: &[LineSegment]) -> Vec<FeatureType> {
+    let mut present = [false; FeatureType::COUNT];
+    for seg in segments {
+        if seg.is_extrusion() {
+            present[seg.feature() as usize] = true;
+        }
+    }
+    FeatureType::ALL
+        .into_iter()
+        .filter(|f| present[*f as usize])
+        .collect()
+}
+
+/// Tools that extrude something, in number order.
+fn present_tools(segments: &[LineSegment]) -> Vec<u8> {
+    let mut present = [false; MAX_TOOLS];
+    for seg in segments {
+        if seg.is_extrusion() {
+            present[seg.tool as usize] = true;
+        }
+    }
+    (0..MAX_TOOLS as u8).filter(|&t| present[t as usize]).collect()
+}
+
+/// Returns the entry under the given screen position of a legend whose rows start at `top`.
+fn legend_hit<T: Copy>(entries: &[T], top: f32, (mx, my): (f32, f32)) -> Option<T> {
+    if !(LEGEND_X..=LEGEND_X + LEGEND_WIDTH).contains(&mx) || my < top {
+        return None;
+    }
+    let row = ((my - top) / LEGEND_ROW_HEIGHT) as usize;
+    entries.get(row).copied()
+}
+
+/// Top of the travel legend, which sits above the retraction key in the bottom-left corner.
+fn travel_legend_top(rows: usize) -> f32 {
+    screen_height() - 52.0 - rows as f32 * LEGEND_ROW_HEIGHT
+}
+
+/// Swatch, number key and label of one legend row, counting rows from `top`; dimmed when hidden.
+fn draw_legend_row(top: f32, row: usize, (r, g, b): (u8, u8, u8), label: &str, shown: bool) -> f32 {
+    let top = top + row as f32 * LEGEND_ROW_HEIGHT;
+    let swatch = if shown {
+        Color::from_rgba(r, g, b, 255)
+    } else {
+        Color::from_rgba(r, g, b, 60)
+    };
+    draw_rectangle(LEGEND_X, top + 3.0, 14.0, 14.0, swatch);
+
+    let key = if row < 9 { format!("{} ", row + 1) } else { "  ".to_string() };
+    draw_text(
+        format!("{}{}", key, label),
+        LEGEND_X + 22.0,
+        top + 15.0,
+        18.0,
+        if shown { WHITE } else { GRAY },
+    );
+    top
+}
+
+fn draw_feature_legend(
+    features: &[FeatureType],
+    visible: &[bool; FeatureType::COUNT],
+    usage: &[Usage; FeatureType::COUNT],
+) {
+    for (i, feature) in features.iter().enumerate() {
+        let top = draw_legend_row(LEGEND_TOP, i, feature.rgb(), feature.name(), visible[*feature as usize]);
+        let length = usage[*feature as usize].length;
+        if length > 0.0 {
+            draw_text(format_length(length), LEGEND_X + LEGEND_WIDTH, top + 15.0, 16.0, GRAY);
+        }
+    }
+}
+
+/// Tool rows in the tool's filament color, with filament use, purge and tool changes.
+fn draw_tool_legend(scene: &Scene, visible: &[bool; MAX_TOOLS]) {
+    let stats = &scene.stats;
+    for (i, &tool) in scene.legend_tools.iter().enumerate() {
+        let tool = tool as usize;
+        let top = draw_legend_row(LEGEND_TOP, i, scene.tool_colors[tool], &format!("T{}", tool), visible[tool]);
+        let mut text = format_length(stats.per_tool[tool].length);
+        let purge = stats.purge_per_tool[tool].length;
+        if purge > 0.0 {
+            text += &format!(" (purge {})", format_length(purge));
+        }
+        let changes = scene.tool_changes.counts[tool];
+        if changes > 0 {
+            text += &format!(" {}x", changes);
+        }
+        draw_text(text, LEGEND_X + 80.0, top + 15.0, 16.0, GRAY);
+    }
+}
+
+/// Travel kinds with their distance, above the retraction key; z-hops also show their count.
+fn draw_travel_legend(scene: &Scene, visible: &[bool; TravelKind::COUNT]) {
+    let stats = &scene.travel_stats;
+    let legend_top = travel_legend_top(scene.legend_travels.len());
+    for (i, &kind) in scene.legend_travels.iter().enumerate() {
+        let top = draw_legend_row(legend_top, i, kind.rgb(), kind.name(), visible[kind as usize]);
+        let mut text = format_length(stats.distance[kind as usize]);
+        if kind == TravelKind::ZHop {
+            text += &format!(" {}x", stats.z_hops());
+        }
+        draw_text(text, LEGEND_X + LEGEND_WIDTH, top + 15.0, 16.0, GRAY);
+    }
+}
+
+/// Names of the retraction kinds in the file in their glyph colors, above the controls line.
+fn draw_retract_key(events: &[RetractEvent]) {
+    let mut x = 10.0;
+    for kind in RetractKind::ALL {
+        let count = events.iter().filter(|e| e.kind == kind).count();
+        if count == 0 {
+            continue;
+        }
+        let (r, g, b) = kind.rgb();
+        let label = format!("{} {}", kind.name(), count);
+        draw_text(&label, x, screen_height() - 32.0, 18.0, Color::from_rgba(r, g, b, 255));
+        x += measure_text(&label, None, 18, 1.0).width + 16.0;
+    }
+}
+
+/// How the layer filter isolates layers around the current one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LayerView {
+    /// Everything up to and including the current layer
+    UpTo,
+    /// Only the current layer
+    Single,
+    /// Layers from the range start up to the current layer
+    Range,
+    /// Current layer at full color, the layers below dimmed
+    Ghosted,
+}
+
+impl LayerView {
+    fn next(self) -> Self {
+        match self {
+            LayerView::UpTo => LayerView::Single,
+            LayerView::Single => LayerView::Range,
+            LayerView::Range => LayerView::Ghosted,
+            LayerView::Ghosted => LayerView::UpTo,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LayerView::UpTo => "Up to layer",
+            LayerView::Single => "Single layer",
+            LayerView::Range => "Layer range",
+            LayerView::Ghosted => "Ghosted below",
+        }
+    }
+}
+
+fn draw_gradient_legend(mode: ColorMode, scale: &ColorScale) {
+    const BAR_HEIGHT: f32 = 200.0;
+    const STEPS: usize = 50;
+
+    draw_text(mode.name(), LEGEND_X, LEGEND_TOP + 15.0, 18.0, WHITE);
+    let top = LEGEND_TOP + 25.0;
+    let step = BAR_HEIGHT / STEPS as f32;
+    for i in 0..STEPS {
+        // Max at the top of the bar
+        let (r, g, b) = gradient(1.0 - i as f32 / (STEPS - 1) as f32);
+        draw_rectangle(LEGEND_X, top + i as f32 * step, 14.0, step + 1.0, Color::from_rgba(r, g, b, 255));
+    }
+    let label = |value: f32| format!("{:.2} {}", value, mode.unit());
+    draw_text(label(scale.max), LEGEND_X + 22.0, top + 10.0, 18.0, WHITE);
+    draw_text(
+        label((scale.min + scale.max) * 0.5),
+        LEGEND_X + 22.0,
+        top + BAR_HEIGHT * 0.5 + 5.0,
+        18.0,
+        WHITE,
+    );
+    draw_text(label(scale.min), LEGEND_X + 22.0, top + BAR_HEIGHT, 18.0, WHITE);
+}
+
+/// Progressive reveal of the segment list in file order, simulating the print.
+struct Playback {
+    enabled: bool,
+    playing: bool,
+    /// Number of segments revealed, fractional while playing
+    cursor: f32,
+    speed: f32,
+}
+
+impl Playback {
+    /// Segments revealed per second at 1x speed
+    const BASE_RATE: f32 = 200.0;
+    const MAX_SPEED: f32 = 1024.0;
+
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            playing: false,
+            cursor: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    fn revealed(&self) -> usize {
+        self.cursor as usize
+    }
+
+    fn advance(&mut self, dt: f32, total: usize) {
+        if self.playing {
+            self.cursor = (self.cursor + dt * Self::BASE_RATE * self.speed).min(total as f32);
+            if self.revealed() >= total {
+                self.playing = false;
+            }
+        }
+    }
+
+    /// Moves the cursor by whole segments and pauses playback.
+    fn step(&mut self, delta: isize, total: usize) {
+        self.playing = false;
+        self.cursor = (self.revealed() as isize + delta).clamp(0, total as isize) as f32;
+    }
+}
+
+/// Planner estimate alongside the slicer's header value, when the file has one.
+fn time_summary(parsed: &ParsedGcode) -> String {
+    match parsed.slicer_time {
+        Some(slicer) => format!(
+            "Estimated time: {} (slicer: {})",
+            format_duration(parsed.print_time),
+            format_duration(slicer)
+        ),
+        None => format!("Estimated time: {}", format_duration(parsed.print_time)),
+    }
+}
+
+/// Filament totals with the per-feature and per-tool breakdown, checked against the slicer.
+fn print_filament_stats(stats: &FilamentStats, tool_changes: &ToolChanges) {
+    println!("Filament: {}", stats.describe(&stats.total));
+    for feature in FeatureType::ALL {
+        let usage = &stats.per_feature[feature as usize];
+        if usage.length > 0.0 {
+            println!("  {}: {}", feature.name(), stats.describe(usage));
+        }
+    }
+    if stats.per_tool.len() > 1 {
+        for (tool, usage) in stats.per_tool.iter().enumerate() {
+            println!(
+                "  T{}: {}, purge {}, {} tool changes",
+                tool,
+                stats.describe(usage),
+                stats.describe(&stats.purge_per_tool[tool]),
+                tool_changes.counts[tool]
+            );
+        }
+    }
+    if let (Some(slicer), Some(deviation)) = (stats.slicer_length, stats.slicer_deviation()) {
+        let warning = if deviation.abs() > FILAMENT_TOLERANCE { " - check extrusion modes" } else { "" };
+        println!(
+            "Slicer filament length: {} ({:+.1}%){}",
+            format_length(slicer),
+            deviation * 100.0,
+            warning
+        );
+    }
+}
+
+/// Direction the baked-in lighting comes from: top-front-right.
+fn light_direction() -> Vec3 {
+    vec3(0.5, 0.7, 0.3).normalize()
+}
+
+/// Maps G-code coordinates into the normalized scene, with G-code Z as the vertical axis.
+fn to_scene(p: Vec3D, center: Vec3D, scale: f32) -> Vec3 {
+    vec3(
+        (p.x - center.x) * scale,
+        (p.z - center.z) * scale,
+        (p.y - center.y) * scale,
+    )
+}
+
+/// Unlit color with 0-1 channels for the extrusion at `index`: the value gradient when a
+/// color scale is active, otherwise the feature or tool color shaded from dark (bottom) to
+/// bright (top).
+fn extrusion_rgb(coloring: Coloring, index: usize, seg: &LineSegment, height_ratio: f32) -> Vec3 {
+    let (r, g, b) = match coloring {
+        // Lighting only, so the colors stay readable against the legend
+        Coloring::Gradient(scale) => {
+            let (r, g, b) = gradient(scale.normalize(scale.values[index]));
+            return vec3(r as f32, g as f32, b as f32) / 255.0;
+        }
+        Coloring::Tool(colors) => colors[seg.tool as usize],
+        Coloring::Feature => seg.feature().rgb(),
+    };
+    vec3(r as f32, g as f32, b as f32) / 255.0 * (0.5 + height_ratio * 0.5)
+}
+
+/// Tube geometry for every visible extrusion, in scene coordinates.
+#[allow(clippy::too_many_arguments)]
+fn build_scene_tubes(
+    toolpath: &Toolpath,
+    layers: &[Layer],
+    coloring: Coloring,
+    visible: &Visibility,
+    bounds: &Bounds,
+    scale: f32,
+    filament_area: f32,
+    light_dir: Vec3,
+) -> Geometry {
+    let center = bounds.center();
+    let mut beads = Vec::new();
+    for layer in layers {
+        for index in layer.range.clone() {
+            let seg = &toolpath.segments[index];
+            if !seg.is_extrusion() || !visible.shows(seg) {
+                continue;
+            }
+            let (start, end) = (toolpath.start(index), toolpath.end(index));
+            let (width, height) = seg.bead_size(start.distance(end), layer.height, filament_area);
+            let height_ratio = (end.z - bounds.min.z) / (bounds.max.z - bounds.min.z);
+            beads.push(Bead {
+                segment: index,
+                start: to_scene(start, center, scale),
+                end: to_scene(end, center, scale),
+                width: width * scale,
+                height: height * scale,
+                color: extrusion_rgb(coloring, index, seg, height_ratio),
+            });
+        }
+    }
+    build_tubes(&beads, light_dir)
+}
+
+/// Line geometry for the whole toolpath in scene coordinates: extrusions and travels.
+///
+/// Hidden features, tools and travel kinds are left out; colors include the directional lighting and height shading.
+fn build_scene_lines(
+    toolpath: &Toolpath,
+    coloring: Coloring,
+    visible: &Visibility,
+    bounds: &Bounds,
+    scale: f32,
+    light_dir: Vec3,
+) -> (Geometry, Geometry) {
+    let center = bounds.center();
+    let mut extrusions = Geometry::default();
+    let mut travels = Geometry::default();
+    for (index, seg) in toolpath.segments.iter().enumerate() {
+        if !visible.shows(seg) {
+            continue;
+        }
+
+        let end = toolpath.end(index);
+        let start_scaled = to_scene(toolpath.start(index), center, scale);
+        let end_scaled = to_scene(end, center, scale);
+
+        // Calculate line direction for lighting
+        let line_dir = (end_scaled - start_scaled).normalize();
+
+        // Simple diffuse lighting: dot product with light direction
+        // Use abs to light both sides of the line
+        let light_intensity = line_dir.dot(light_dir).abs();
+        // Combine with ambient lighting (0.6 base + 0.4 from directional) - brighter overall
+        let lighting = 0.6 + light_intensity * 0.4;
+
+        // Calculate color with height-based shading for depth perception
+        let height_ratio = (end.z - bounds.min.z) / (bounds.max.z - bounds.min.z);
+        let (geometry, color) = if seg.is_extrusion() {
+            let rgb = extrusion_rgb(coloring, index, seg, height_ratio) * lighting;
+            (&mut extrusions, Color::new(rgb.x, rgb.y, rgb.z, 1.0))
+        } else {
+            // Travels colored by kind, slightly dimmed with height
+            let brightness = (0.6 + height_ratio * 0.4) * lighting; // Brighter base
+            let (r, g, b) = seg.travel.rgb();
+            let color = Color::from_rgba(
+                (r as f32 * brightness) as u8,
+                (g as f32 * brightness) as u8,
+                (b as f32 * brightness) as u8,
+                180,
+            );
+            (&mut travels, color)
+        };
+
+        geometry.begin_segment(index);
+        let base = geometry.base();
+        geometry.vertices.push(GpuVertex::new(start_scaled, color));
+        geometry.vertices.push(GpuVertex::new(end_scaled, color));
+        geometry.indices.extend_from_slice(&[base, base + 1]);
+    }
+    (extrusions, travels)
+}
+
+/// Flat feature-colored extrusion lines for the loading preview, in millimetres.
+fn build_preview_lines(part: &Toolpath) -> Geometry {
+    let mut geometry = Geometry::default();
+    for (index, seg) in part.segments.iter().enumerate() {
+        if !seg.is_extrusion() {
+            continue;
+        }
+        let (r, g, b) = seg.feature().rgb();
+        let color = Color::from_rgba(r, g, b, 255);
+        geometry.begin_segment(index);
+        let base = geometry.base();
+        geometry.vertices.push(GpuVertex::new(to_scene(part.start(index), Vec3D::zero(), 1.0), color));
+        geometry.vertices.push(GpuVertex::new(to_scene(part.end(index), Vec3D::zero(), 1.0), color));
+        geometry.indices.extend_from_slice(&[base, base + 1]);
+    }
+    geometry
+}
+
+/// Progress bar across the middle of the window for a load in progress.
+fn draw_load_progress(filename: &str, fraction: f32) {
+    let width = 400.0_f32.min(screen_width() - 40.0);
+    let x = (screen_width() - width) * 0.5;
+    let y = screen_height() * 0.5;
+    let label = format!("Loading {}: {:.0}%  (Esc to cancel)", filename, fraction * 100.0);
+    draw_text(&label, x, y - 10.0, 20.0, WHITE);
+    draw_rectangle(x, y, width, 16.0, Color::from_rgba(50, 50, 65, 230));
+    draw_rectangle(x, y, width * fraction.clamp(0.0, 1.0), 16.0, Color::from_rgba(90, 160, 255, 255));
+    draw_rectangle_lines(x, y, width, 16.0, 1.0, LIGHTGRAY);
+}
+
+/// Parses, filters and measures `filename` into a scene ready to display, without logging anything.
+///
+/// The purge region in `options` overrides the one from the printer profile matching the file.
+fn build_scene(filename: &str, options: SceneOptions, progress: &LoadProgress) -> Result<Scene> {
+    let parsed = parse_gcode(filename, progress)?;
+    let time_text = time_summary(&parsed);
+
+    let mut toolpath = parsed.toolpath;
+    if toolpath.is_empty() {
+        anyhow::bail!("No valid G-code movements found in file");
+    }
+
+    let purge_region = options.purge_region.or_else(|| parsed.metadata.printer_model.as_deref().and_then(profile::purge_region));
+    let print_range = find_print_range(&toolpath, &parsed.markers, purge_region);
+    travel::classify(&mut toolpath, print_range.clone());
+
+    let bounds = compute_bounds(&toolpath, 0..toolpath.len());
+    let print_bounds = compute_bounds(&toolpath, print_range.clone());
+    // A print section without extrusions can't be framed; fall back to the whole file
+    let print_bounds = if print_bounds.max_dimension() > 0.0 { print_bounds } else { bounds.clone() };
+
+    let layers = build_layers(&toolpath, parsed.metadata.first_layer_height);
+
+    let filament_diameter = parsed.metadata.filament_diameter.unwrap_or(1.75);
+    let mut stats =
+        FilamentStats::compute(&toolpath, &layers, &parsed.tool_changes, filament_diameter, &parsed.metadata);
+    if let Some(price) = options.filament_price {
+        stats.reprice(price);
+    }
+
+    let travel_stats = TravelStats::compute(&toolpath, &layers, 0..toolpath.len());
+    let source = SourceIndex::build(filename)?;
+
+    let thumbnail = thumbnail::best_png(&parsed.thumbnails)
+        .and_then(|t| Image::from_file_with_format(&t.data, Some(ImageFormat::Png)).ok());
+
+    Ok(Scene {
+        filename: filename.to_owned(),
+        legend_features: present_features(&toolpath.segments),
+        legend_tools: present_tools(&toolpath.segments),
+        tool_changes: parsed.tool_changes,
+        tool_colors: tool::colors(&parsed.metadata),
+        retractions: parsed.retractions,
+        legend_travels: travel_stats.present(),
+        travel_stats,
+        source,
+        toolpath,
+        layers,
+        print_range,
+        bounds,
+        print_bounds,
+        print_time: parsed.print_time,
+        slicer_time: parsed.slicer_time,
+        time_text,
+        filament_diameter,
+        stats,
+        thumbnail,
+        metadata: parsed.metadata,
+    })
+}
+
+/// Builds the scene for the viewer, summarizing it on the console.
+fn load_scene(filename: &str, options: SceneOptions, progress: &LoadProgress) -> Result<Scene> {
+    println!("Loading G-code file: {}", filename);
+    let scene = build_scene(filename, options, progress)?;
+
+    println!("Parsed {} line segments", scene.toolpath.len());
+    println!("{}", scene.time_text);
+    println!(
+        "Print section: segments {}..{} of {}",
+        scene.print_range.start,
+        scene.print_range.end,
+        scene.toolpath.len()
+    );
+    let print_bounds = &scene.print_bounds;
+    println!(
+        "Bounds: ({:.1}, {:.1}, {:.1}) to ({:.1}, {:.1}, {:.1})",
+        print_bounds.min.x,
+        print_bounds.min.y,
+        print_bounds.min.z,
+        print_bounds.max.x,
+        print_bounds.max.y,
+        print_bounds.max.z
+    );
+    println!("Layers: {}", scene.layers.len());
+    println!(
+        "Travel: {}, {} z-hops",
+        format_length(scene.travel_stats.total_distance()),
+        scene.travel_stats.z_hops()
+    );
+    print_filament_stats(&scene.stats, &scene.tool_changes);
+
+    Ok(scene)
+}
+
+/// Starts loading `filename`, abandoning any load still in progress.
+fn start_load(loader: &mut Option<Loader>, filename: String, options: &Options, preview: bool) {
+    if let Some(active) = loader.take() {
+        active.cancel();
+    }
+    *loader = Some(Loader::start(filename, options.scene, preview));
+}
+
+/// One-line text box along the bottom of the window for typing a file path.
+fn draw_path_prompt(text: &str) {
+    let top = screen_height() - 60.0;
+    draw_rectangle(0.0, top, screen_width(), 30.0, Color::from_rgba(40, 40, 55, 240));
+    draw_text(
+        format!("Open: {}_   (Enter=Open, Esc=Cancel)", text),
+        10.0,
+        top + 21.0,
+        20.0,
+        WHITE,
+    );
+}
+
+/// A picked segment and the G-code line it came from.
+struct Inspection {
+    segment: usize,
+    /// Raw text of the source line, or why it couldn't be read
+    text: String,
+}
+
+impl Inspection {
+    fn new(scene: &Scene, segment: usize) -> Self {
+        let text = scene.source.line(scene.toolpath.segments[segment].line);
+        Self { segment, text }
+    }
+}
+
+/// Source line and attributes of the picked segment, in the bottom corner left of `right`.
+fn draw_inspection(scene: &Scene, inspection: &Inspection, right: f32) {
+    const WIDTH: f32 = 440.0;
+    const ROW_HEIGHT: f32 = 20.0;
+    let toolpath = &scene.toolpath;
+    let seg = &toolpath.segments[inspection.segment];
+    let layer = scene.layers.iter().find(|l| l.range.contains(&inspection.segment));
+    let kind = if seg.is_extrusion() {
+        format!("Feature: {}", seg.feature().name())
+    } else {
+        format!("Travel: {}", seg.travel.name())
+    };
+    let rows = [
+        format!("Line {}  (segment {})", seg.line, inspection.segment),
+        inspection.text.clone(),
+        kind,
+        match layer {
+            Some(layer) => format!("Layer: {} (Z {:.2}mm)", layer.number, layer.z),
+            None => format!("Layer: {}", seg.layer),
+        },
+        format!("Feedrate: {:.1}mm/s", seg.feedrate),
+        format!("E: {:+.5}mm over {:.3}mm", seg.e, toolpath.length(inspection.segment)),
+    ];
+
+    let x = right - WIDTH - 10.0;
+    let top = screen_height() - 60.0 - rows.len() as f32 * ROW_HEIGHT;
+    draw_rectangle(x, top, WIDTH, rows.len() as f32 * ROW_HEIGHT + 10.0, Color::from_rgba(30, 30, 45, 230));
+    draw_rectangle_lines(x, top, WIDTH, rows.len() as f32 * ROW_HEIGHT + 10.0, 1.0, YELLOW);
+    for (i, row) in rows.iter().enumerate() {
+        let color = match i {
+            0 => YELLOW,
+            1 => Color::from_rgba(130, 210, 255, 255),
+            _ => WHITE,
+        };
+        let text: String = row.chars().take(52).collect();
+        draw_text(&text, x + 10.0, top + 20.0 + i as f32 * ROW_HEIGHT, 18.0, color);
+    }
+}
+
+/// The slicer's preview image in the top-right corner, at most 160 px across.
+fn draw_thumbnail(texture: &Texture2D) {
+    const MAX_SIZE: f32 = 160.0;
+    let fit = (MAX_SIZE / texture.width().max(texture.height())).min(2.0);
+    let size = vec2(texture.width(), texture.height()) * fit;
+    let x = screen_width() - size.x - 10.0;
+    let y = 40.0;
+    draw_rectangle(x - 2.0, y - 2.0, size.x + 4.0, size.y + 4.0, Color::from_rgba(40, 40, 55, 200));
+    draw_texture_ex(
+        texture,
+        x,
+        y,
+        WHITE,
+        DrawTextureParams { dest_size: Some(size), ..Default::default() },
+    );
+}
+
 fn window_conf() -> Conf {
     Conf {
         window_title: "GSoda - G-code 3D Viewer".to_owned(),
@@ -234,93 +1543,290 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() -> Result<()> {
+fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <gcode-file>", args[0]);
-        eprintln!("\nControls:");
-        eprintln!("  Mouse drag: Rotate camera");
-        eprintln!("  Scroll:     Zoom in/out");
-        eprintln!("  R:          Reset camera");
-        eprintln!("  L:          Toggle layer filtering");
-        eprintln!("  M:          Toggle travel moves");
-        eprintln!("  S:          Toggle axis indicator");
-        eprintln!("  Up/Down:    Adjust visible layers");
-        eprintln!("  Esc:        Quit");
+    // Subcommands run headless, so the window is only opened once we know it's wanted
+    let result = match Command::parse(args.iter().skip(1).cloned()) {
+        Ok(Command::View(options)) => {
+            macroquad::Window::from_config(window_conf(), run_viewer(options));
+            Ok(())
+        }
+        Ok(Command::Info { file }) => print_info(&file),
+        Ok(Command::Analyze(options)) => match analyze::run(&options) {
+            Ok(true) => Ok(()),
+            // Checks failed; the report already says which
+            Ok(false) => std::process::exit(2),
+            Err(err) => Err(err),
+        },
+        Ok(Command::Thumbnails { file, output_dir }) => extract_thumbnails(&file, output_dir.as_deref()),
+        Ok(Command::Render(options)) => render::run(&options),
+        Err(err) => {
+            eprintln!("{}\n", err);
+            print_usage(&args[0]);
+            std::process::exit(1);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {:#}", err);
         std::process::exit(1);
     }
+}
 
-    let filename = &args[1];
-    println!("Loading G-code file: {}", filename);
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} [options] <gcode-file>", program);
+    eprintln!("       {} --info <gcode-file>", program);
+    eprintln!("       {} analyze <gcode-file> [--json] [--fail-on <checks>|all] [--max-time <duration>] [--max-filament <g>]", program);
+    eprintln!("       {} thumbnails <gcode-file> [-o <dir>]", program);
+    eprintln!("       {} render <gcode-file> -o <png> [--view iso|front|back|left|right|top|bottom] [--size WxH] [--lines] [--travels] [--no-filter]", program);
+    eprintln!("\nOptions:");
+    eprintln!("  --no-filter                   Show the start/end G-code like the rest of the print");
+    eprintln!("  --purge-region X0,Y0,X1,Y1    Purge line area, for files without start/end markers");
+    eprintln!("  --filament-price <per kg>     Filament price for the cost estimate");
+    eprintln!("\nControls:");
+    eprintln!("  Mouse drag: Rotate camera");
+    eprintln!("  Click:      Inspect the segment under the cursor");
+    eprintln!("  Scroll:     Zoom in/out");
+    eprintln!("  R:          Reset camera");
+    eprintln!("  L:          Toggle layer filtering");
+    eprintln!("  M:          Toggle travel moves");
+    eprintln!("  S:          Toggle axis indicator");
+    eprintln!("  E:          Toggle retraction markers");
+    eprintln!("  Up/Down:    Step visible layers one at a time");
+    eprintln!("  K:          Cycle layer view (up to / single / range / ghosted)");
+    eprintln!("  PgUp/PgDn:  Move the layer range start");
+    eprintln!("  C:          Cycle color mode (feature, tool, speed, flow, width, height, fan, temp, layer time)");
+    eprintln!("  V:          Toggle volumetric tube rendering");
+    eprintln!("  P:          Toggle toolpath playback");
+    eprintln!("  Space:      Play/pause playback");
+    eprintln!("  Left/Right: Step playback by one move");
+    eprintln!("  +/-:        Playback speed");
+    eprintln!("  1-9/Click:  Toggle feature types (or tools, in tool color mode) in the legend");
+    eprintln!("  Shift+1-5:  Toggle travel kinds in the travel legend");
+    eprintln!("  0:          Show all feature types, tools and travel kinds");
+    eprintln!("  F:          Toggle dimming of the start/end G-code");
+    eprintln!("  T:          Toggle the slicer thumbnail");
+    eprintln!("  G:          Toggle the G-code source panel; click a line to pick its move");
+    eprintln!("  I:          Show the slicer settings (type to search)");
+    eprintln!("  O:          Open a file by path (or drop one onto the window)");
+    eprintln!("  F5:         Reload the current file");
+    eprintln!("  W:          Toggle automatic reload when the file changes");
+    eprintln!("  Esc:        Cancel loading / Quit");
+}
 
-    let segments = parse_gcode(filename)?;
-    println!("Parsed {} line segments", segments.len());
-    
-    let segments = filter_priming_lines(&segments);
-    println!("After filtering priming: {} segments", segments.len());
+/// Prints the slicer settings, the ones the viewer understands first.
+fn print_info(filename: &str) -> Result<()> {
+    let metadata = metadata::extract(filename)?;
+    if metadata.settings.is_empty() && metadata.slicer.is_none() {
+        anyhow::bail!("No slicer settings found in {}", filename);
+    }
+    for (label, value) in metadata.summary() {
+        println!("{:<24}{}", format!("{}:", label), value);
+    }
+    println!("\nSettings ({}):", metadata.settings.len());
+    for setting in &metadata.settings {
+        println!("{} = {}", setting.key, setting.value);
+    }
+    Ok(())
+}
 
-    if segments.is_empty() {
-        anyhow::bail!("No valid G-code movements found in file");
+/// Writes every embedded thumbnail to `<name>_<W>x<H>.<ext>`, next to the G-code by default.
+fn extract_thumbnails(filename: &str, output_dir: Option<&str>) -> Result<()> {
+    let thumbnails = thumbnail::extract(filename)?;
+    if thumbnails.is_empty() {
+        anyhow::bail!("No thumbnails found in {}", filename);
     }
 
-    let bounds = compute_bounds(&segments);
-    let center = bounds.center();
-    let scale = 2.0 / bounds.max_dimension();
-    let initial_distance = 3.0;
+    let path = Path::new(filename);
+    let stem = path.file_stem().map_or("thumbnail".into(), |s| s.to_string_lossy());
+    let dir = match output_dir {
+        Some(dir) => Path::new(dir),
+        None => path.parent().unwrap_or(Path::new("")),
+    };
+    for (i, thumb) in thumbnails.iter().enumerate() {
+        let mut name = format!("{}_{}x{}", stem, thumb.width, thumb.height);
+        // Slicers may embed the same size twice; keep both
+        if thumbnails[..i]
+            .iter()
+            .any(|t| (t.width, t.height, t.format) == (thumb.width, thumb.height, thumb.format))
+        {
+            name = format!("{}_{}", name, i);
+        }
+        let out = dir.join(format!("{}.{}", name, thumb.format.extension()));
+        std::fs::write(&out, &thumb.data)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", out.display(), e))?;
+        println!("Wrote {}", out.display());
+    }
+    Ok(())
+}
 
-    println!(
-        "Bounds: ({:.1}, {:.1}, {:.1}) to ({:.1}, {:.1}, {:.1})",
-        bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z
-    );
+async fn run_viewer(options: Options) {
+
+    // File last asked for, which reload and the watcher follow
+    let mut current_file = options.file.clone();
+    let mut loader = Some(Loader::start(current_file.clone(), options.scene, true));
+    let mut watcher = FileWatcher::new(&current_file);
+    let mut auto_reload = true;
+    // Text typed into the open-file prompt while it is shown
+    let mut path_prompt: Option<String> = None;
+    let mut scene: Option<Scene> = None;
+    // Error or cancellation of the last load, shown until the next one succeeds
+    let mut load_message: Option<String> = None;
+    // Extrusion bounds of the pieces previewed so far, to frame the partial toolpath
+    let mut preview_bounds = Bounds::new();
+    let initial_distance = Camera::DEFAULT_DISTANCE;
 
-    let max_z = bounds.max.z;
     let mut camera = Camera::new(initial_distance);
     let mut layer_filter_enabled = false;
-    let mut layer_filter_z = max_z;
+    let mut layer_index = 0;
+    let mut layer_view = LayerView::UpTo;
+    let mut range_start = 0;
+    let mut playback = Playback::new();
     let mut show_travel_moves = true; // Changed to true by default
     let mut show_axis = true; // Show axis indicator by default
+    let mut show_retractions = true;
+    let mut color_mode = ColorMode::Feature;
+    let mut color_scale: Option<ColorScale> = None;
+    let mut show_tubes = false;
+    let mut show_thumbnail = true;
+    let mut thumbnail_texture: Option<Texture2D> = None;
+    let mut settings_panel: Option<SettingsPanel> = None;
+    // Dim the start/end G-code and frame the print itself
+    let mut filter_sections = options.filter;
+    let light_dir = light_direction();
+    let mut visibility = Visibility::all();
+    let mut renderer = ToolpathRenderer::new();
+    // Colors and visibility are baked into the GPU buffers, so any change needs a rebuild
+    let mut scene_dirty = true;
+    let mut tubes_built = false;
 
     let mut last_mouse_pos: Option<(f32, f32)> = None;
+    // Where the left button went down, unless that toggled a legend row
+    let mut click_start: Option<(f32, f32)> = None;
+    let mut picked: Option<Inspection> = None;
+    let mut source_panel: Option<SourcePanel> = None;
 
     loop {
-        if is_key_pressed(KeyCode::Escape) {
-            break;
-        }
+        // Shortcuts are off while typing a path
+        let typing = path_prompt.is_some() || settings_panel.is_some();
+        let pressed = |key| !typing && is_key_pressed(key);
 
-        if is_key_pressed(KeyCode::R) {
-            camera.reset(initial_distance);
-            println!("Camera reset");
+        if pressed(KeyCode::Escape) {
+            match &loader {
+                Some(active) => active.cancel(),
+                None => break,
+            }
         }
 
-        if is_key_pressed(KeyCode::L) {
-            layer_filter_enabled = !layer_filter_enabled;
-            println!("Layer filter: {}", if layer_filter_enabled { "ON" } else { "OFF" });
+        // Open a dropped file, a typed path, or reload the current one
+        let mut open = get_dropped_files()
+            .into_iter()
+            .find_map(|file| file.path)
+            .map(|path| path.to_string_lossy().into_owned());
+        if let Some(text) = &mut path_prompt {
+            while let Some(c) = get_char_pressed() {
+                if !c.is_control() {
+                    text.push(c);
+                }
+            }
+            if is_key_pressed(KeyCode::Backspace) {
+                text.pop();
+            }
+            if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+                let path = text.trim().to_owned();
+                if !path.is_empty() {
+                    open = Some(path);
+                }
+                path_prompt = None;
+            } else if is_key_pressed(KeyCode::Escape) {
+                path_prompt = None;
+            }
         }
-
-        if is_key_pressed(KeyCode::M) {
-            show_travel_moves = !show_travel_moves;
-            println!("Travel moves: {}", if show_travel_moves { "ON" } else { "OFF" });
+        if pressed(KeyCode::O) {
+            // Start from the current file's folder
+            let folder = Path::new(&current_file)
+                .parent()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .filter(|dir| !dir.is_empty());
+            path_prompt = Some(folder.map_or_else(String::new, |dir| format!("{}/", dir)));
+            clear_input_queue();
         }
-
-        if is_key_pressed(KeyCode::S) {
-            show_axis = !show_axis;
-            println!("Axis indicator: {}", if show_axis { "ON" } else { "OFF" });
+        if pressed(KeyCode::W) {
+            auto_reload = !auto_reload;
+            println!("Auto reload: {}", if auto_reload { "ON" } else { "OFF" });
+        }
+        let file_changed = watcher.poll() && auto_reload;
+        if file_changed {
+            println!("{} changed on disk, reloading", current_file);
+        }
+        if let Some(path) = open {
+            watcher = FileWatcher::new(&path);
+            current_file = path;
+            start_load(&mut loader, current_file.clone(), &options, scene.is_none());
+        } else if pressed(KeyCode::F5) || file_changed {
+            start_load(&mut loader, current_file.clone(), &options, scene.is_none());
         }
 
-        if layer_filter_enabled {
-            if is_key_pressed(KeyCode::Up) {
-                layer_filter_z = (layer_filter_z + 0.5).min(max_z);
-                println!("Layer filter Z: {:.2}", layer_filter_z);
+        if let Some(active) = &loader {
+            for part in active.progress().take_preview() {
+                for index in 0..part.len() {
+                    if part.segments[index].is_extrusion() {
+                        preview_bounds.expand(part.start(index));
+                        preview_bounds.expand(part.end(index));
+                    }
+                }
+                renderer.push_preview(build_preview_lines(&part));
             }
-            if is_key_pressed(KeyCode::Down) {
-                layer_filter_z = (layer_filter_z - 0.5).max(0.0);
-                println!("Layer filter Z: {:.2}", layer_filter_z);
+            if let Some(result) = active.poll() {
+                renderer.clear_preview();
+                preview_bounds = Bounds::new();
+                match result {
+                    Ok(loaded) => {
+                        // Reloading the same file keeps the view where it was
+                        let reload = scene.as_ref().is_some_and(|old| old.filename == loaded.filename);
+                        if reload {
+                            layer_index = layer_index.min(loaded.layers.len() - 1);
+                            range_start = range_start.min(layer_index);
+                            playback.cursor = playback.cursor.min(loaded.toolpath.len() as f32);
+                        } else {
+                            layer_index = loaded.layers.len() - 1;
+                            range_start = 0;
+                            playback = Playback::new();
+                        }
+                        color_scale = ColorScale::compute(
+                            color_mode,
+                            &loaded.toolpath,
+                            &loaded.layers,
+                            loaded.filament_diameter,
+                        );
+                        scene_dirty = true;
+                        tubes_built = false;
+                        load_message = None;
+                        thumbnail_texture = loaded.thumbnail.as_ref().map(Texture2D::from_image);
+                        picked = None;
+                        if source_panel.is_some() {
+                            source_panel = Some(SourcePanel::new());
+                        }
+                        scene = Some(loaded);
+                    }
+                    Err(err) => {
+                        eprintln!("{}: {:#}", active.filename, err);
+                        load_message = Some(format!("{}: {:#}", active.filename, err));
+                    }
+                }
+                loader = None;
             }
         }
 
+        if pressed(KeyCode::R) {
+            camera.reset(initial_distance);
+            println!("Camera reset");
+        }
+
+        // The source panel takes the mouse while over it, except for a drag that began outside
+        let over_panel = source_panel.is_some() && SourcePanel::contains(mouse_position());
+
         // Mouse rotation
-        if is_mouse_button_down(MouseButton::Left) {
+        if is_mouse_button_down(MouseButton::Left) && (last_mouse_pos.is_some() || !over_panel) {
             let (mx, my) = mouse_position();
             if let Some((last_x, last_y)) = last_mouse_pos {
                 let dx = mx - last_x;
@@ -333,81 +1839,385 @@ async fn main() -> Result<()> {
             last_mouse_pos = None;
         }
 
-        // Mouse zoom
+        // Mouse zoom; the settings panel scrolls instead while it is open, and the source panel under the mouse
         let (_, wheel_y) = mouse_wheel();
-        if wheel_y != 0.0 {
+        if wheel_y != 0.0 && settings_panel.is_none() && !over_panel {
             camera.distance = (camera.distance - wheel_y * 0.1).max(0.5);
         }
 
-        clear_background(Color::from_rgba(20, 20, 30, 255));
-
         // Setup 3D camera
-        set_camera(&Camera3D {
-            position: camera.position(),
-            target: camera.target,
-            up: vec3(0.0, 1.0, 0.0),
-            fovy: 45.0,
-            projection: Projection::Perspective,
-            ..Default::default()
-        });
+        let cam_3d = camera.camera_3d();
 
-        // Define light direction (from top-front-right, normalized)
-        let light_dir = vec3(0.5, 0.7, 0.3).normalize();
+        let Some(scene) = &scene else {
+            // Nothing loaded yet: show what has been parsed so far, framed like the final scene
+            clear_background(BACKGROUND);
+            if preview_bounds.max_dimension() > 0.0 {
+                set_camera(&cam_3d);
+                let model = Mat4::from_scale(Vec3::splat(2.0 / preview_bounds.max_dimension()))
+                    * Mat4::from_translation(-to_scene(preview_bounds.center(), Vec3D::zero(), 1.0));
+                renderer.draw(
+                    cam_3d.matrix() * model,
+                    &[DrawRange { batch: Batch::Preview, range: 0..usize::MAX, tint: [1.0; 4] }],
+                );
+                set_default_camera();
+            }
+            if let Some(active) = &loader {
+                draw_load_progress(&active.filename, active.progress().fraction());
+            }
+            if let Some(message) = &load_message {
+                draw_text(message, 10.0, 25.0, 20.0, Color::from_rgba(255, 120, 120, 255));
+                draw_text(
+                    "Drop a file or O=Open | F5=Retry | Esc=Quit",
+                    10.0,
+                    screen_height() - 10.0,
+                    18.0,
+                    LIGHTGRAY,
+                );
+            }
+            if let Some(text) = &path_prompt {
+                draw_path_prompt(text);
+            }
+            next_frame().await;
+            continue;
+        };
+        let toolpath = &scene.toolpath;
+        let layers = &scene.layers;
+        let bounds = if filter_sections { &scene.print_bounds } else { &scene.bounds };
+        let legend_features = &scene.legend_features;
+        let center = bounds.center();
+        let scale = 2.0 / bounds.max_dimension();
+        let filament_area = std::f32::consts::PI * (scene.filament_diameter * 0.5).powi(2);
 
-        // Draw toolpath
-        for seg in &segments {
-            if layer_filter_enabled && seg.layer_z > layer_filter_z {
-                continue;
+        if pressed(KeyCode::L) {
+            layer_filter_enabled = !layer_filter_enabled;
+            println!("Layer filter: {}", if layer_filter_enabled { "ON" } else { "OFF" });
+        }
+
+        if pressed(KeyCode::F) {
+            filter_sections = !filter_sections;
+            // Framing changes, so the geometry is rebuilt around the new bounds
+            scene_dirty = true;
+            println!("Start/end G-code filter: {}", if filter_sections { "ON" } else { "OFF" });
+        }
+
+        if pressed(KeyCode::I) {
+            settings_panel = Some(SettingsPanel::default());
+            clear_input_queue();
+        } else if let Some(panel) = &mut settings_panel {
+            if !panel.update(&scene.metadata) {
+                settings_panel = None;
             }
+        }
 
-            // Skip travel moves if not enabled
-            if !seg.is_extrusion && !show_travel_moves {
-                continue;
+        if pressed(KeyCode::T) {
+            show_thumbnail = !show_thumbnail;
+            println!("Thumbnail: {}", if show_thumbnail { "ON" } else { "OFF" });
+        }
+
+        if pressed(KeyCode::G) {
+            source_panel = match source_panel {
+                Some(_) => None,
+                None => {
+                    let mut panel = SourcePanel::new();
+                    if let Some(inspection) = &picked {
+                        panel.select(toolpath.segments[inspection.segment].line, &scene.source);
+                    }
+                    Some(panel)
+                }
+            };
+        }
+        if let Some(panel) = source_panel.as_mut().filter(|_| settings_panel.is_none()) {
+            // A line picks the first segment it made; other lines just stay highlighted
+            if let Some(line) = panel.update(&scene.source) {
+                let segments = toolpath.line_segments(line);
+                picked = (!segments.is_empty()).then(|| Inspection::new(scene, segments.start));
             }
+        }
 
-            let start_scaled = vec3(
-                (seg.start.x - center.x) * scale,
-                (seg.start.z - center.z) * scale,
-                (seg.start.y - center.y) * scale,
-            );
-            let end_scaled = vec3(
-                (seg.end.x - center.x) * scale,
-                (seg.end.z - center.z) * scale,
-                (seg.end.y - center.y) * scale,
+        if pressed(KeyCode::M) {
+            show_travel_moves = !show_travel_moves;
+            println!("Travel moves: {}", if show_travel_moves { "ON" } else { "OFF" });
+        }
+
+        if pressed(KeyCode::S) {
+            show_axis = !show_axis;
+            println!("Axis indicator: {}", if show_axis { "ON" } else { "OFF" });
+        }
+
+        if pressed(KeyCode::E) {
+            show_retractions = !show_retractions;
+            println!("Retraction markers: {}", if show_retractions { "ON" } else { "OFF" });
+        }
+
+        if pressed(KeyCode::C) {
+            color_mode = color_mode.next();
+            color_scale = ColorScale::compute(color_mode, toolpath, layers, scene.filament_diameter);
+            scene_dirty = true;
+            println!("Color mode: {}", color_mode.name());
+        }
+
+        if pressed(KeyCode::V) {
+            show_tubes = !show_tubes;
+            println!("Volumetric tubes: {}", if show_tubes { "ON" } else { "OFF" });
+        }
+
+        if pressed(KeyCode::P) {
+            playback.enabled = !playback.enabled;
+            if playback.enabled {
+                playback.cursor = 0.0;
+                playback.playing = true;
+            }
+            println!("Playback: {}", if playback.enabled { "ON" } else { "OFF" });
+        }
+
+        if playback.enabled {
+            if pressed(KeyCode::Space) {
+                if playback.revealed() >= toolpath.len() {
+                    playback.cursor = 0.0;
+                }
+                playback.playing = !playback.playing;
+            }
+            if pressed(KeyCode::Right) {
+                playback.step(1, toolpath.len());
+            }
+            if pressed(KeyCode::Left) {
+                playback.step(-1, toolpath.len());
+            }
+            if pressed(KeyCode::Equal) || pressed(KeyCode::KpAdd) {
+                playback.speed = (playback.speed * 2.0).min(Playback::MAX_SPEED);
+                println!("Playback speed: {}x", playback.speed);
+            }
+            if pressed(KeyCode::Minus) || pressed(KeyCode::KpSubtract) {
+                playback.speed = (playback.speed / 2.0).max(1.0 / 16.0);
+                println!("Playback speed: {}x", playback.speed);
+            }
+            playback.advance(get_frame_time(), toolpath.len());
+        }
+
+        // Legend toggles: number keys follow the legend order, which lists tools in the tool
+        // color mode and feature types in the feature mode; gradient modes have no toggles.
+        // With Shift they follow the travel legend
+        let clicked = is_mouse_button_pressed(MouseButton::Left);
+        let mut legend_clicked = false;
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if show_travel_moves {
+            let mut toggled = NUMBER_KEYS
+                .iter()
+                .zip(&scene.legend_travels)
+                .find(|(key, _)| shift && pressed(**key))
+                .map(|(_, kind)| *kind);
+            if clicked {
+                let top = travel_legend_top(scene.legend_travels.len());
+                let hit = legend_hit(&scene.legend_travels, top, mouse_position());
+                legend_clicked |= hit.is_some();
+                toggled = toggled.or(hit);
+            }
+            if let Some(kind) = toggled {
+                let shown = &mut visibility.travels[kind as usize];
+                *shown = !*shown;
+                scene_dirty = true;
+                println!("{}: {}", kind.name(), if *shown { "ON" } else { "OFF" });
+            }
+        }
+        if color_mode == ColorMode::Tool {
+            let mut toggled = NUMBER_KEYS
+                .iter()
+                .zip(&scene.legend_tools)
+                .find(|(key, _)| !shift && pressed(**key))
+                .map(|(_, tool)| *tool);
+            if clicked {
+                let hit = legend_hit(&scene.legend_tools, LEGEND_TOP, mouse_position());
+                legend_clicked |= hit.is_some();
+                toggled = toggled.or(hit);
+            }
+            if let Some(tool) = toggled {
+                let shown = &mut visibility.tools[tool as usize];
+                *shown = !*shown;
+                scene_dirty = true;
+                println!("T{}: {}", tool, if *shown { "ON" } else { "OFF" });
+            }
+        } else if color_mode == ColorMode::Feature {
+            let mut toggled = NUMBER_KEYS
+                .iter()
+                .zip(legend_features)
+                .find(|(key, _)| !shift && pressed(**key))
+                .map(|(_, feature)| *feature);
+            if clicked {
+                let hit = legend_hit(legend_features, LEGEND_TOP, mouse_position());
+                legend_clicked |= hit.is_some();
+                toggled = toggled.or(hit);
+            }
+            if let Some(feature) = toggled {
+                let shown = &mut visibility.features[feature as usize];
+                *shown = !*shown;
+                scene_dirty = true;
+                println!("{}: {}", feature.name(), if *shown { "ON" } else { "OFF" });
+            }
+        }
+        if pressed(KeyCode::Key0) {
+            visibility = Visibility::all();
+            scene_dirty = true;
+            println!("All feature types, tools and travel kinds: ON");
+        }
+        if clicked && !legend_clicked && !typing && !over_panel {
+            click_start = Some(mouse_position());
+        }
+
+        if layer_filter_enabled {
+            if pressed(KeyCode::Up) && layer_index + 1 < layers.len() {
+                layer_index += 1;
+                println!("Layer {} (Z {:.2})", layers[layer_index].number, layers[layer_index].z);
+            }
+            if pressed(KeyCode::Down) && layer_index > 0 {
+                layer_index -= 1;
+                range_start = range_start.min(layer_index);
+                println!("Layer {} (Z {:.2})", layers[layer_index].number, layers[layer_index].z);
+            }
+            if pressed(KeyCode::K) {
+                layer_view = layer_view.next();
+                println!("Layer view: {}", layer_view.name());
+            }
+            if layer_view == LayerView::Range {
+                if pressed(KeyCode::PageUp) && range_start < layer_index {
+                    range_start += 1;
+                    println!("Range start: layer {}", layers[range_start].number);
+                }
+                if pressed(KeyCode::PageDown) && range_start > 0 {
+                    range_start -= 1;
+                    println!("Range start: layer {}", layers[range_start].number);
+                }
+            }
+        }
+
+        let coloring = Coloring::new(color_mode, color_scale.as_ref(), &scene.tool_colors);
+        if scene_dirty {
+            let (extrusions, travels) = build_scene_lines(
+                toolpath,
+                coloring,
+                &visibility,
+                bounds,
+                scale,
+                light_dir,
             );
+            renderer.set_lines(extrusions, travels);
+            renderer.set_markers(retract::build_markers(&scene.retractions, center, scale));
+        }
+        if !show_tubes {
+            renderer.set_tubes(None);
+            tubes_built = false;
+        } else if scene_dirty || !tubes_built {
+            renderer.set_tubes(Some(build_scene_tubes(
+                toolpath,
+                layers,
+                coloring,
+                &visibility,
+                bounds,
+                scale,
+                filament_area,
+                light_dir,
+            )));
+            tubes_built = true;
+        }
+        scene_dirty = false;
 
-            // Calculate line direction for lighting
-            let line_dir = (end_scaled - start_scaled).normalize();
-            
-            // Simple diffuse lighting: dot product with light direction
-            // Use abs to light both sides of the line
-            let light_intensity = line_dir.dot(light_dir).abs();
-            // Combine with ambient lighting (0.6 base + 0.4 from directional) - brighter overall
-            let lighting = 0.6 + light_intensity * 0.4;
-
-            // Calculate color with height-based shading for depth perception
-            let height_ratio = (seg.layer_z - bounds.min.z) / (bounds.max.z - bounds.min.z);
-            let color = if seg.is_extrusion {
-                // Blue extrusion with gradient from dark (bottom) to bright (top)
-                let brightness = (0.5 + height_ratio * 0.5) * lighting; // Apply lighting, brighter base
-                Color::from_rgba(
-                    (100.0 * brightness) as u8,
-                    (200.0 * brightness) as u8,
-                    (255.0 * brightness) as u8,
-                    255
-                )
-            } else {
-                // Red travel moves, slightly dimmed with height
-                let brightness = (0.6 + height_ratio * 0.4) * lighting; // Brighter base
-                Color::from_rgba(
-                    (255.0 * brightness) as u8,
-                    (100.0 * brightness) as u8,
-                    (100.0 * brightness) as u8,
-                    180
-                )
-            };
+        clear_background(BACKGROUND);
+
+        set_camera(&cam_3d);
 
-            draw_line_3d(start_scaled, end_scaled, color);
+        // Layers are contiguous in file order, so every view is a range of the segment list
+        let current = &layers[layer_index].range;
+        let (visible, ghost_end) = if !layer_filter_enabled {
+            (0..toolpath.len(), 0)
+        } else {
+            match layer_view {
+                LayerView::UpTo => (0..current.end, 0),
+                LayerView::Single => (current.clone(), 0),
+                LayerView::Range => (layers[range_start].range.start..current.end, 0),
+                LayerView::Ghosted => (0..current.end, current.start),
+            }
+        };
+        let visible = if playback.enabled {
+            visible.start..visible.end.min(playback.revealed()).max(visible.start)
+        } else {
+            visible
+        };
+
+        // Draw toolpath: a few ranges over the static buffers instead of one call per segment
+        let solid = ghost_end.max(visible.start)..visible.end;
+        // With the filter on, start and end G-code are dimmed like ghosted layers
+        let print = if filter_sections { scene.print_range.clone() } else { 0..toolpath.len() };
+        let inside = solid.start.max(print.start)..solid.end.min(print.end);
+
+        // A click that doesn't turn the camera inspects the segment under the cursor
+        if is_mouse_button_released(MouseButton::Left) {
+            let (mx, my) = mouse_position();
+            if let Some((x, y)) = click_start.take().filter(|&(x, y)| (mx - x).abs() + (my - y).abs() < 4.0) {
+                let segment = pick::nearest_segment(
+                    toolpath,
+                    solid.clone(),
+                    |seg| visibility.shows(seg) && (seg.is_extrusion() || show_travel_moves),
+                    cam_3d.matrix(),
+                    (x, y),
+                    (screen_width(), screen_height()),
+                    center,
+                    scale,
+                );
+                picked = segment.map(|segment| Inspection::new(scene, segment));
+                if let Some(inspection) = &picked {
+                    let line = toolpath.segments[inspection.segment].line;
+                    println!("Line {}: {}", line, inspection.text);
+                    if let Some(panel) = &mut source_panel {
+                        panel.select(line, &scene.source);
+                    }
+                }
+            }
+        }
+        let mut draws = vec![DrawRange {
+            batch: if show_tubes { Batch::Tubes } else { Batch::Extrusions },
+            range: inside.clone(),
+            tint: [1.0; 4],
+        }];
+        if show_travel_moves {
+            draws.push(DrawRange { batch: Batch::Travels, range: inside.clone(), tint: [1.0; 4] });
+        }
+        if show_retractions {
+            draws.push(DrawRange { batch: Batch::Markers, range: inside, tint: [1.0; 4] });
+        }
+        let outside = [solid.start..solid.end.min(print.start), solid.start.max(print.end)..solid.end];
+        for range in outside {
+            draws.push(DrawRange { batch: Batch::Extrusions, range: range.clone(), tint: [0.5, 0.5, 0.5, 0.25] });
+            if show_travel_moves {
+                draws.push(DrawRange { batch: Batch::Travels, range, tint: [0.5, 0.5, 0.5, 0.25] });
+            }
+        }
+        // Ghosted layers are dimmed lines, drawn last so they blend over the solid part
+        let ghost = visible.start..ghost_end;
+        draws.push(DrawRange { batch: Batch::Extrusions, range: ghost.clone(), tint: [0.5, 0.5, 0.5, 0.15] });
+        if show_travel_moves {
+            draws.push(DrawRange { batch: Batch::Travels, range: ghost, tint: [0.5, 0.5, 0.5, 0.15] });
+        }
+        renderer.draw(cam_3d.matrix(), &draws);
+
+        // Every chord of a picked arc is highlighted, as they come from the same line
+        if let Some(inspection) = &picked {
+            for index in toolpath.line_segments(toolpath.segments[inspection.segment].line) {
+                let start = to_scene(toolpath.start(index), center, scale);
+                let end = to_scene(toolpath.end(index), center, scale);
+                draw_line_3d(start, end, YELLOW);
+                draw_sphere(start, 0.006, None, YELLOW);
+                draw_sphere(end, 0.006, None, YELLOW);
+            }
+        }
+
+        // Nozzle marker at the end of the last revealed move
+        if playback.enabled && playback.revealed() > 0 {
+            let nozzle_scaled = to_scene(toolpath.end(playback.revealed() - 1), center, scale);
+            draw_sphere(nozzle_scaled, 0.015, None, Color::from_rgba(255, 255, 255, 255));
+            draw_line_3d(
+                nozzle_scaled,
+                nozzle_scaled + vec3(0.0, 0.08, 0.0),
+                Color::from_rgba(200, 200, 200, 255),
+            );
         }
 
         // Collect 3D label positions before switching to 2D
@@ -581,15 +2391,6 @@ async fn main() -> Result<()> {
         set_default_camera();
 
         // Draw 3D labels as 2D text
-        let cam_3d = Camera3D {
-            position: camera.position(),
-            target: camera.target,
-            up: vec3(0.0, 1.0, 0.0),
-            fovy: 45.0,
-            projection: Projection::Perspective,
-            ..Default::default()
-        };
-        
         for (pos_3d, label, color) in &label_3d_positions {
             // Manual MVP projection
             let view = Mat4::look_at_rh(cam_3d.position, cam_3d.target, cam_3d.up);
@@ -623,26 +2424,262 @@ async fn main() -> Result<()> {
         let model_size_y = bounds.max.y - bounds.min.y;
         let model_size_z = bounds.max.z - bounds.min.z;
 
+        let file_name = Path::new(&scene.filename)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&scene.filename);
         let ui_text = format!(
-            "Segments: {} | Size: {:.1}x{:.1}x{:.1}mm | Travel: {} | Axis: {}",
-            segments.len(),
+            "{} | Segments: {} | Size: {:.1}x{:.1}x{:.1}mm | {} | Filament: {} | Travel: {} | Axis: {}",
+            file_name,
+            toolpath.len(),
             model_size_x,
             model_size_y,
             model_size_z,
+            scene.time_text,
+            scene.stats.describe(&scene.stats.total),
             if show_travel_moves { "ON" } else { "OFF" },
             if show_axis { "ON" } else { "OFF" }
         );
+        let tool_changes = scene.tool_changes.total();
+        let ui_text = if tool_changes > 0 {
+            format!("{} | Tool changes: {}", ui_text, tool_changes)
+        } else {
+            ui_text
+        };
+        let ui_text = if layer_filter_enabled {
+            let layer = &layers[layer_index];
+            let range = if layer_view == LayerView::Range {
+                format!("{}..", layers[range_start].number)
+            } else {
+                String::new()
+            };
+            format!(
+                "{} | {}: {}{}/{} Z {:.2}mm (h {:.2}) {} {} {} z-hops",
+                ui_text,
+                layer_view.name(),
+                range,
+                layer.number,
+                layers[layers.len() - 1].number,
+                layer.z,
+                layer.height,
+                format_duration(layer.time),
+                format_length(scene.stats.per_layer[layer_index].length),
+                scene.travel_stats.z_hops_per_layer[layer_index]
+            )
+        } else {
+            ui_text
+        };
+        let ui_text = if playback.enabled {
+            format!(
+                "{} | Playback: {}/{} {}x F{:.0}mm/s {}",
+                ui_text,
+                playback.revealed(),
+                toolpath.len(),
+                playback.speed,
+                toolpath.segments[playback.revealed().saturating_sub(1)].feedrate,
+                if playback.playing { "PLAY" } else { "PAUSED" }
+            )
+        } else {
+            ui_text
+        };
         draw_text(&ui_text, 10.0, 25.0, 20.0, WHITE);
+        match coloring {
+            Coloring::Gradient(scale) => draw_gradient_legend(color_mode, scale),
+            Coloring::Tool(_) => draw_tool_legend(scene, &visibility.tools),
+            Coloring::Feature => draw_feature_legend(legend_features, &visibility.features, &scene.stats.per_feature),
+        }
+        if show_travel_moves {
+            draw_travel_legend(scene, &visibility.travels);
+        }
+        if show_retractions {
+            draw_retract_key(&scene.retractions);
+        }
+        let mut right = screen_width();
+        if let Some(panel) = &mut source_panel {
+            let cursor = playback.revealed().checked_sub(1).filter(|_| playback.enabled);
+            panel.set_cursor(cursor.map(|index| toolpath.segments[index].line), &scene.source);
+            panel.draw(&scene.source);
+            right = SourcePanel::left();
+        }
+        if let Some(inspection) = &picked {
+            draw_inspection(scene, inspection, right);
+        }
+        match &settings_panel {
+            Some(panel) => panel.draw(&scene.metadata),
+            None => {
+                // The source panel covers the thumbnail's corner
+                let shown = show_thumbnail && source_panel.is_none();
+                if let Some(texture) = thumbnail_texture.as_ref().filter(|_| shown) {
+                    draw_thumbnail(texture);
+                }
+            }
+        }
         draw_text(
-            "Controls: Drag=Rotate | Scroll=Zoom | R=Reset | L=Layers | M=Travel | S=Axis | Up/Down=Filter | Esc=Quit",
+            "Controls: Drag=Rotate | Click=Inspect | Scroll=Zoom | R=Reset | L=Layers | M=Travel | S=Axis | E=Retracts | Up/Down=Layer | K=Layer view | P=Playback | C=Color | V=Tubes | 1-9=Features/Tools | Shift+1-5=Travels | F=Filter | T=Thumbnail | G=G-code | I=Settings | O=Open | F5=Reload | Esc=Quit",
             10.0,
             screen_height() - 10.0,
             18.0,
             LIGHTGRAY,
         );
+        if let Some(message) = &load_message {
+            draw_text(message, 10.0, 45.0, 20.0, Color::from_rgba(255, 120, 120, 255));
+        }
+        if let Some(active) = &loader {
+            draw_load_progress(&active.filename, active.progress().fraction());
+        }
+        if let Some(text) = &path_prompt {
+            draw_path_prompt(text);
+        }
 
         next_frame().await;
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `gcode` to a file of its own in the temp directory and returns its path.
+    fn write_temp(name: &str, gcode: &str) -> String {
+        let path = env::temp_dir().join(format!("gsoda-{}-{}.gcode", std::process::id(), name));
+        std::fs::write(&path, gcode).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn parse(name: &str, gcode: &str) -> ParsedGcode {
+        let path = write_temp(name, gcode);
+        let parsed = parse_gcode(&path, &LoadProgress::default()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        parsed
+    }
+
+    fn scene(name: &str, gcode: &str) -> Scene {
+        let path = write_temp(name, gcode);
+        let scene = build_scene(&path, SceneOptions::default(), &LoadProgress::default()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        scene
+    }
+
+    fn extrusions(toolpath: &Toolpath) -> usize {
+        toolpath.segments.iter().filter(|s| s.is_extrusion()).count()
+    }
+
+    /// G91 then M83 then G90 before three moves of E1 each.
+    const RELATIVE_THEN_G90: &str = "G91\nM83\nG90\nG1 X10 Y0 E1\nG1 X20 E1\nG1 X30 E1\n";
+
+    #[test]
+    fn marlin_g90_makes_e_absolute() {
+        let parsed = parse("marlin-g90", &format!("{}; gcode_flavor = marlin2\n", RELATIVE_THEN_G90));
+        // E sits at 1 after the first move, so the other two don't extrude
+        assert_eq!(extrusions(&parsed.toolpath), 1);
+    }
+
+    #[test]
+    fn klipper_g90_keeps_m83() {
+        let parsed = parse("klipper-g90", &format!("{}; gcode_flavor = klipper\n", RELATIVE_THEN_G90));
+        assert_eq!(extrusions(&parsed.toolpath), 3);
+    }
+
+    #[test]
+    fn reprap_g91_leaves_e_alone() {
+        let gcode = "; gcode_flavor = reprapfirmware\nM82\nG91\nG1 X10 E1\nG1 X10 E1\n";
+        assert_eq!(extrusions(&parse("reprap-g91", gcode).toolpath), 1);
+    }
+
+    #[test]
+    fn flavor_from_trailing_config() {
+        let mut gcode = "G1 X1 Y1\n".repeat(FLAVOR_SCAN_BYTES as usize / 8);
+        gcode.push_str("; gcode_flavor = klipper\n");
+        let path = write_temp("flavor-tail", &gcode);
+        assert_eq!(FirmwareFlavor::scan(&path), Some(FirmwareFlavor::Klipper));
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(FirmwareFlavor::from_setting("RepRapFirmware"), FirmwareFlavor::RepRap);
+        assert_eq!(FirmwareFlavor::from_setting("marlin2"), FirmwareFlavor::Marlin);
+    }
+
+    #[test]
+    fn g92_offsets() {
+        let gcode = "M82\nG1 X10 Y10 Z0.2\nG92 X0 Y0\nG1 X5 Y0 E5\nG92 E0\nG1 X5 Y5 E1\nG92.1\nG1 X0 Y0\n";
+        let parsed = parse("g92", gcode);
+        let toolpath = &parsed.toolpath;
+        assert_eq!(toolpath.len(), 4);
+        // Logical X5 Y0 is 10mm over from where G92 zeroed X and Y
+        assert_eq!(toolpath.end(1), Vec3D::new(15.0, 10.0, 0.2));
+        assert_eq!(toolpath.end(2), Vec3D::new(15.0, 15.0, 0.2));
+        // After G92 E0 the absolute E1 pushes 1mm, not 4mm back
+        assert!(toolpath.segments[2].is_extrusion());
+        assert_eq!(toolpath.segments[2].e, 1.0);
+        // G92.1 drops the offset again
+        assert_eq!(toolpath.end(3), Vec3D::new(0.0, 0.0, 0.2));
+    }
+
+    /// Cura-style start: a purge line at Z0.28 before `;LAYER:0` prints at Z0.2.
+    const PURGE_THEN_LAYERS: &str = "\
+M82
+G1 Z0.28 F3000
+G1 X0.1 Y20 F5000
+G1 X0.1 Y200 E15 F1500
+G92 E0
+;LAYER:0
+G0 X100 Y100 Z0.2
+G1 X120 Y100 E1
+G1 X120 Y120 E2
+;LAYER:1
+G0 X120 Y120 Z0.4
+G1 X100 Y120 E3
+";
+
+    #[test]
+    fn first_layer_takes_its_own_height() {
+        let scene = scene("first-layer", PURGE_THEN_LAYERS);
+        let layers = &scene.layers;
+        assert_eq!(layers.len(), 2);
+        // The purge line joins the first layer without setting its Z or height
+        assert_eq!(layers[0].range.start, 0);
+        assert_eq!(layers[0].z, 0.2);
+        assert_eq!(layers[0].height, 0.2);
+        assert!((layers[1].height - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn first_layer_height_from_metadata() {
+        let gcode = format!("{}; initial_layer_print_height = 0.25\n", PURGE_THEN_LAYERS);
+        assert_eq!(scene("first-layer-setting", &gcode).layers[0].height, 0.25);
+    }
+
+    #[test]
+    fn single_layer() {
+        let gcode = "M83\nG1 Z0.3\nG1 X10 E1\n;LAYER:0\nG1 Z0.2\nG1 X20 E1\n";
+        assert_eq!(scene("single-layer", gcode).layers.len(), 1);
+    }
+
+    #[test]
+    fn z_hops_return_to_the_print() {
+        let gcode = "\
+M83
+G1 Z0.2
+G1 X10 Y10
+G1 X20 Y10 E1
+G1 Z0.6
+G0 X30 Y20
+G1 Z0.2
+G1 X40 Y20 E1
+G1 Z0.4
+G1 X10 Y10 Z0.4
+G1 X20 Y10 E1
+G1 Z50
+";
+        let scene = scene("z-hops", gcode);
+        let kinds: Vec<_> = scene
+            .toolpath
+            .segments
+            .iter()
+            .filter(|s| !s.is_extrusion())
+            .map(|s| s.travel)
+            .collect();
+        use TravelKind::*;
+        assert_eq!(kinds, [Travel, Travel, ZHop, Rapid, ZHop, LayerChange, Travel, Travel]);
+        assert_eq!(scene.travel_stats.z_hops(), 1);
+    }
 }
@@ -0,0 +1,364 @@
+use crate::profile::Region;
+use crate::reader::{self, Line};
+use anyhow::Result;
+
+/// One slicer setting as written in the file.
+#[derive(Clone, Debug)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+}
+
+/// Slicer settings from a file's header, config block or footer.
+///
+/// The common ones are looked up under each slicer's key names; everything else is kept
+/// as text in `settings`.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    /// Slicer name and version
+    pub slicer: Option<String>,
+    pub printer_model: Option<String>,
+    pub layer_height: Option<f32>,
+    pub first_layer_height: Option<f32>,
+    pub nozzle_diameter: Option<f32>,
+    /// Print temperatures in °C, after the first layer
+    pub nozzle_temperature: Option<f32>,
+    pub bed_temperature: Option<f32>,
+    pub filament_type: Option<String>,
+    /// In mm
+    pub filament_diameter: Option<f32>,
+    /// In g/cm³
+    pub filament_density: Option<f32>,
+    /// Price per kg
+    pub filament_cost: Option<f32>,
+    /// `#RRGGBB` per filament slot
+    pub filament_colors: Vec<String>,
+    /// In mm
+    pub brim_width: Option<f32>,
+    /// In percent
+    pub infill_density: Option<f32>,
+    /// Bed area the printer can reach, as the bounding box of the slicer's bed outline
+    pub printable_area: Option<Region>,
+    /// In mm
+    pub printable_height: Option<f32>,
+    /// The slicer's total filament length in mm
+    pub total_filament_length: Option<f32>,
+    /// The slicer's total filament weight in g
+    pub total_filament_weight: Option<f32>,
+    /// Every setting in file order; later ones override earlier ones with the same key
+    pub settings: Vec<Setting>,
+}
+
+impl Metadata {
+    /// Value of the last setting named `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.settings
+            .iter()
+            .rev()
+            .find(|s| s.key == key)
+            .map(|s| s.value.as_str())
+    }
+
+    /// Settings whose key or value contains `query`, ignoring case.
+    pub fn matching<'a>(&'a self, query: &str) -> impl Iterator<Item = &'a Setting> + 'a {
+        let query = query.to_lowercase();
+        self.settings.iter().filter(move |s| {
+            query.is_empty() || s.key.to_lowercase().contains(&query) || s.value.to_lowercase().contains(&query)
+        })
+    }
+
+    /// The typed settings as labelled text, skipping those the file doesn't have.
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        let mm = |v: Option<f32>| v.map(|v| format!("{}mm", v));
+        let celsius = |v: Option<f32>| v.map(|v| format!("{}°C", v));
+        let colors = (!self.filament_colors.is_empty()).then(|| self.filament_colors.join(" "));
+        [
+            ("Slicer", self.slicer.clone()),
+            ("Printer", self.printer_model.clone()),
+            ("Layer height", mm(self.layer_height)),
+            ("First layer height", mm(self.first_layer_height)),
+            ("Nozzle diameter", mm(self.nozzle_diameter)),
+            ("Nozzle temperature", celsius(self.nozzle_temperature)),
+            ("Bed temperature", celsius(self.bed_temperature)),
+            ("Filament type", self.filament_type.clone()),
+            ("Filament diameter", mm(self.filament_diameter)),
+            ("Filament density", self.filament_density.map(|v| format!("{}g/cm³", v))),
+            ("Filament cost", self.filament_cost.map(|v| format!("{}/kg", v))),
+            ("Filament colors", colors),
+            ("Brim width", mm(self.brim_width)),
+            ("Infill density", self.infill_density.map(|v| format!("{}%", v))),
+            (
+                "Printable area",
+                self.printable_area.map(|r| format!("{}x{}mm", r.max_x - r.min_x, r.max_y - r.min_y)),
+            ),
+            ("Printable height", mm(self.printable_height)),
+            ("Slicer filament length", mm(self.total_filament_length)),
+            ("Slicer filament weight", self.total_filament_weight.map(|v| format!("{}g", v))),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| Some((label, value?)))
+        .collect()
+    }
+
+    /// First of the keys the file has, as text without quotes.
+    fn text(&self, keys: &[&str]) -> Option<String> {
+        let value = keys.iter().find_map(|key| self.get(key))?;
+        let value = value.trim().trim_matches('"');
+        (!value.is_empty()).then(|| value.to_owned())
+    }
+
+    /// First of the keys the file has, as a number; per-extruder lists give the first entry.
+    fn number(&self, keys: &[&str]) -> Option<f32> {
+        keys.iter().find_map(|key| parse_number(self.get(key)?))
+    }
+
+    /// Every entry of the first of the keys the file has, for per-filament lists like `1.24,1.27`.
+    pub fn numbers(&self, keys: &[&str]) -> Vec<f32> {
+        keys.iter()
+            .find_map(|key| self.get(key))
+            .map(|value| value.split([',', ';']).filter_map(parse_number).collect())
+            .unwrap_or_default()
+    }
+
+    /// Bambu and Orca keep one bed temperature per plate type.
+    fn plate_temperature(&self) -> Option<f32> {
+        let key = match self.get("curr_bed_type")? {
+            "Cool Plate" => "cool_plate_temp",
+            "Engineering Plate" => "eng_plate_temp",
+            "Textured PEI Plate" => "textured_plate_temp",
+            "Supertack Plate" => "supertack_plate_temp",
+            _ => "hot_plate_temp",
+        };
+        self.number(&[key])
+    }
+
+    fn fill_typed_fields(&mut self) {
+        self.printer_model = self.text(&["printer_model", "machine_name", "TARGET_MACHINE.NAME"]);
+        self.layer_height = self.number(&["layer_height", "Layer height"]);
+        self.first_layer_height = self.number(&["initial_layer_print_height", "first_layer_height", "layer_height_0"]);
+        self.nozzle_diameter = self.number(&["nozzle_diameter", "machine_nozzle_size"]);
+        self.nozzle_temperature = self.number(&["nozzle_temperature", "temperature", "material_print_temperature"]);
+        self.bed_temperature = self
+            .plate_temperature()
+            .or_else(|| self.number(&["bed_temperature", "material_bed_temperature"]));
+        self.filament_type = self.text(&["filament_type", "material_type"]);
+        self.filament_diameter = self.number(&["filament_diameter", "material_diameter"]);
+        self.filament_density = self.number(&["filament_density", "material_density"]);
+        self.filament_cost = self.number(&["filament_cost"]);
+        self.filament_colors = self
+            .text(&["filament_colour", "extruder_colour"])
+            .map(|value| value.split([',', ';']).map(|c| c.trim().to_owned()).collect())
+            .unwrap_or_default();
+        self.brim_width = self.number(&["brim_width"]);
+        self.infill_density = self.number(&["sparse_infill_density", "fill_density", "infill_sparse_density"]);
+        self.printable_area = self
+            .text(&["printable_area", "bed_shape"])
+            .and_then(|outline| parse_outline(&outline))
+            .or_else(|| {
+                let (width, depth) = (self.number(&["machine_width"])?, self.number(&["machine_depth"])?);
+                Some(Region { min_x: 0.0, min_y: 0.0, max_x: width, max_y: depth })
+            });
+        self.printable_height = self.number(&["printable_height", "max_print_height", "machine_height"]);
+        self.total_filament_length = self
+            .number(&["total filament length [mm]", "filament used [mm]"])
+            // Cura's header gives metres, e.g. `Filament used: 1.2345m`
+            .or_else(|| self.number(&["Filament used"]).map(|m| m * 1000.0));
+        self.total_filament_weight = self.number(&[
+            "total filament weight [g]",
+            "total filament used [g]",
+            "filament used [g]",
+        ]);
+    }
+}
+
+/// Leading number of a value such as `0.2`, `15%`, `0.4,0.4` or `1.2m`.
+fn parse_number(value: &str) -> Option<f32> {
+    let first = value.split([',', ';']).next()?.trim();
+    let end = first
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+' || c == 'e'))
+        .unwrap_or(first.len());
+    first[..end].parse().ok()
+}
+
+/// Bounding box of a bed outline such as `0x0,220x0,220x220,0x220`.
+fn parse_outline(outline: &str) -> Option<Region> {
+    let mut region: Option<Region> = None;
+    for point in outline.split(',') {
+        let (x, y) = point.trim().split_once('x')?;
+        let (x, y): (f32, f32) = (x.parse().ok()?, y.parse().ok()?);
+        let r = region.get_or_insert(Region { min_x: x, min_y: y, max_x: x, max_y: y });
+        r.min_x = r.min_x.min(x);
+        r.min_y = r.min_y.min(y);
+        r.max_x = r.max_x.max(x);
+        r.max_y = r.max_y.max(y);
+    }
+    region
+}
+
+/// Gathers settings from comment lines as they stream past.
+///
+/// `key = value` lines are settings wherever they appear (Bambu/Orca config block,
+/// PrusaSlicer footer); `key: value` lines only in Bambu's header block or before the first
+/// G-code, as Cura writes them, since the body uses that form for per-move annotations.
+#[derive(Default)]
+pub struct MetadataCollector {
+    settings: Vec<Setting>,
+    slicer: Option<String>,
+    in_header: bool,
+    /// Cura's `;SETTING_3` footer, a JSON-escaped profile split over several lines
+    cura_settings: String,
+}
+
+impl MetadataCollector {
+    /// Takes a trimmed comment; `before_codes` is true until the first G-code line.
+    pub fn push_comment(&mut self, comment: &str, before_codes: bool) {
+        if comment == "HEADER_BLOCK_START" {
+            self.in_header = true;
+        } else if comment == "HEADER_BLOCK_END" {
+            self.in_header = false;
+        } else if let Some(part) = comment.strip_prefix("SETTING_3 ") {
+            self.cura_settings.push_str(part);
+        } else if let Some(slicer) = comment
+            .strip_prefix("generated by ")
+            .or_else(|| comment.strip_prefix("Generated with "))
+        {
+            // PrusaSlicer and Orca add ` on <date> at <time>`
+            let slicer = slicer.split(" on ").next().unwrap_or(slicer);
+            self.slicer.get_or_insert_with(|| slicer.trim().to_owned());
+        } else if let Some((key, value)) = comment
+            .split_once(" = ")
+            .or_else(|| Some((comment.strip_suffix(" =")?, "")))
+        {
+            self.push(key, value);
+        } else if self.in_header || before_codes {
+            match comment.split_once(':') {
+                Some((key, value)) => self.push(key, value),
+                // Bambu's header opens with the bare slicer name and version
+                None if self.in_header => {
+                    self.slicer.get_or_insert_with(|| comment.to_owned());
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn push(&mut self, key: &str, value: &str) {
+        let key = key.trim();
+        if !key.is_empty() {
+            self.settings.push(Setting {
+                key: key.to_owned(),
+                value: value.trim().to_owned(),
+            });
+        }
+    }
+
+    pub fn finish(mut self) -> Metadata {
+        // The profile comes as ini text with escaped line breaks: `...\\nlayer_height = 0.2\\n...`
+        let cura_settings = std::mem::take(&mut self.cura_settings);
+        for line in cura_settings.split("\\\\n") {
+            if let Some((key, value)) = line.split_once(" = ") {
+                self.push(key, value);
+            }
+        }
+
+        let mut metadata = Metadata {
+            slicer: self.slicer,
+            settings: self.settings,
+            ..Default::default()
+        };
+        metadata.fill_typed_fields();
+        metadata
+    }
+}
+
+/// Reads only the settings out of a G-code file.
+pub fn extract(filename: &str) -> Result<Metadata> {
+    let mut collector = MetadataCollector::default();
+    let mut before_codes = true;
+    reader::read_lines(
+        filename,
+        |_, line| match line {
+            Line::Comment(comment) => collector.push_comment(comment.trim(), before_codes),
+            Line::Codes(_) => before_codes = false,
+        },
+        |_| true,
+    )?;
+    Ok(collector.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(comments: &[&str], before_codes: bool) -> Metadata {
+        let mut collector = MetadataCollector::default();
+        for comment in comments {
+            collector.push_comment(comment, before_codes);
+        }
+        collector.finish()
+    }
+
+    #[test]
+    fn numbers_with_units_and_lists() {
+        assert_eq!(parse_number("0.2"), Some(0.2));
+        assert_eq!(parse_number("15%"), Some(15.0));
+        assert_eq!(parse_number("0.4,0.6"), Some(0.4));
+        assert_eq!(parse_number("1.2m"), Some(1.2));
+        assert_eq!(parse_number("auto"), None);
+    }
+
+    #[test]
+    fn outline_bounds() {
+        let region = parse_outline("0x0,220x0,220x220,0x220").unwrap();
+        assert_eq!((region.min_x, region.min_y, region.max_x, region.max_y), (0.0, 0.0, 220.0, 220.0));
+        assert!(parse_outline("0x0,220").is_none());
+    }
+
+    #[test]
+    fn config_block_settings() {
+        let metadata = collect(
+            &[
+                "generated by PrusaSlicer 2.8.1 on 2025-01-01 at 10:00:00 UTC",
+                "first_layer_height = 0.3",
+                "layer_height = 0.2",
+                "filament_colour = #FF0000;#00FF00",
+                "bed_shape = 0x0,250x0,250x210,0x210",
+                "filament used [mm] = 1234.5",
+            ],
+            false,
+        );
+        assert_eq!(metadata.slicer.as_deref(), Some("PrusaSlicer 2.8.1"));
+        assert_eq!(metadata.first_layer_height, Some(0.3));
+        assert_eq!(metadata.layer_height, Some(0.2));
+        assert_eq!(metadata.filament_colors, ["#FF0000", "#00FF00"]);
+        assert_eq!(metadata.printable_area.map(|r| (r.max_x, r.max_y)), Some((250.0, 210.0)));
+        assert_eq!(metadata.total_filament_length, Some(1234.5));
+    }
+
+    #[test]
+    fn colon_settings_only_in_the_header() {
+        let header = collect(&["FLAVOR:Marlin", "Filament used: 1.5m", "Layer height: 0.2"], true);
+        assert_eq!(header.get("FLAVOR"), Some("Marlin"));
+        assert_eq!(header.total_filament_length, Some(1500.0));
+        assert_eq!(header.layer_height, Some(0.2));
+
+        // In the body the same form annotates moves
+        let body = collect(&["HEIGHT:0.2", "TYPE:WALL-OUTER"], false);
+        assert!(body.settings.is_empty());
+    }
+
+    #[test]
+    fn plate_temperature() {
+        let metadata = collect(
+            &["curr_bed_type = Textured PEI Plate", "hot_plate_temp = 55", "textured_plate_temp = 65"],
+            false,
+        );
+        assert_eq!(metadata.bed_temperature, Some(65.0));
+    }
+
+    #[test]
+    fn later_settings_override() {
+        let metadata = collect(&["layer_height = 0.2", "layer_height = 0.28"], false);
+        assert_eq!(metadata.layer_height, Some(0.28));
+    }
+}
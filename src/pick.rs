@@ -0,0 +1,87 @@
+use crate::{to_scene, LineSegment, Toolpath, Vec3D};
+use macroquad::prelude::*;
+use std::ops::Range;
+
+/// How far from the cursor a segment may pass and still be picked, in pixels.
+const PICK_RADIUS: f32 = 6.0;
+/// Segments this close to the cursor, in pixels, are under it; the front one of them wins.
+const HIT_RADIUS: f32 = 2.0;
+
+/// Line of sight from the camera through a point on the screen, in scene coordinates.
+pub struct Ray {
+    origin: Vec3,
+    /// Unit length
+    direction: Vec3,
+}
+
+impl Ray {
+    /// Ray through the pixel position `(x, y)` of a `width` × `height` viewport, found by
+    /// unprojecting it onto the near and far planes of `view_proj`.
+    pub fn through(view_proj: Mat4, (x, y): (f32, f32), (width, height): (f32, f32)) -> Self {
+        let ndc = vec2(x / width * 2.0 - 1.0, 1.0 - y / height * 2.0);
+        let inverse = view_proj.inverse();
+        let near = inverse.project_point3(ndc.extend(-1.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        Self {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    /// Closest approach to the segment from `a` to `b`, as (distance, depth along the ray).
+    fn closest_approach(&self, a: Vec3, b: Vec3) -> (f32, f32) {
+        let u = b - a;
+        let w = a - self.origin;
+        let (uu, ud) = (u.dot(u), u.dot(self.direction));
+        // Parameter along the segment of the closest point, clamped to its ends
+        let denominator = uu - ud * ud;
+        let s = if denominator > 1e-12 {
+            ((ud * self.direction.dot(w) - u.dot(w)) / denominator).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let point = a + u * s;
+        let depth = (point - self.origin).dot(self.direction).max(0.0);
+        (point.distance(self.origin + self.direction * depth), depth)
+    }
+}
+
+/// The segment under the cursor, among those in `range` that `shows` accepts.
+///
+/// Of the segments within [`HIT_RADIUS`] pixels of the cursor the one nearest the camera
+/// wins, as that is the one drawn there; failing that, the closest within [`PICK_RADIUS`].
+#[allow(clippy::too_many_arguments)]
+pub fn nearest_segment(
+    toolpath: &Toolpath,
+    range: Range<usize>,
+    shows: impl Fn(&LineSegment) -> bool,
+    view_proj: Mat4,
+    cursor: (f32, f32),
+    viewport: (f32, f32),
+    center: Vec3D,
+    scale: f32,
+) -> Option<usize> {
+    let ray = Ray::through(view_proj, cursor, viewport);
+    // Size of a pixel per unit of depth, from the angle between rays one pixel apart
+    let next = Ray::through(view_proj, (cursor.0 + 1.0, cursor.1), viewport);
+    let pixel = ray.direction.angle_between(next.direction).tan();
+
+    // (segment, depth) of the front hit, and (segment, miss in pixels) of the closest near miss
+    let mut front: Option<(usize, f32)> = None;
+    let mut closest: Option<(usize, f32)> = None;
+    for index in range {
+        if !shows(&toolpath.segments[index]) {
+            continue;
+        }
+        let a = to_scene(toolpath.start(index), center, scale);
+        let b = to_scene(toolpath.end(index), center, scale);
+        let (distance, depth) = ray.closest_approach(a, b);
+        let miss = distance / (pixel * depth).max(f32::EPSILON);
+        if miss <= HIT_RADIUS && front.is_none_or(|(_, d)| depth < d) {
+            front = Some((index, depth));
+        } else if miss <= PICK_RADIUS && closest.is_none_or(|(_, m)| miss < m) {
+            closest = Some((index, miss));
+        }
+    }
+    front.or(closest).map(|(index, _)| index)
+}
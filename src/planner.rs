@@ -0,0 +1,398 @@
+use std::collections::VecDeque;
+
+/// Axis order used by the limit arrays: X, Y, Z, E.
+const AXES: usize = 4;
+
+/// Firmware motion limits, seeded from M201/M203/M204/M205 in the file.
+#[derive(Clone, Copy, Debug)]
+pub struct MotionLimits {
+    /// Per-axis maximum feedrate in mm/s (M203)
+    pub max_feedrate: [f32; AXES],
+    /// Per-axis maximum acceleration in mm/s² (M201)
+    pub max_accel: [f32; AXES],
+    /// M204 P (or S): acceleration for extruding moves
+    pub print_accel: f32,
+    /// M204 R: acceleration for E-only moves
+    pub retract_accel: f32,
+    /// M204 T (or S): acceleration for travel moves
+    pub travel_accel: f32,
+    /// Per-axis classic jerk in mm/s (M205 X/Y/Z/E)
+    pub jerk: [f32; AXES],
+    /// M205 J; `None` selects classic jerk for junction speeds
+    pub junction_deviation: Option<f32>,
+}
+
+impl Default for MotionLimits {
+    /// Marlin's stock configuration, used until the file sets its own limits.
+    fn default() -> Self {
+        Self {
+            max_feedrate: [300.0, 300.0, 5.0, 25.0],
+            max_accel: [3000.0, 3000.0, 100.0, 10000.0],
+            print_accel: 3000.0,
+            retract_accel: 3000.0,
+            travel_accel: 3000.0,
+            jerk: [10.0, 10.0, 0.3, 5.0],
+            junction_deviation: Some(0.013),
+        }
+    }
+}
+
+impl MotionLimits {
+    /// Applies an M201/M203/M204/M205 command; other codes are ignored.
+    pub fn apply(&mut self, code: u32, args: impl Iterator<Item = (char, f32)>) {
+        for (letter, value) in args {
+            let axis = match letter {
+                'X' => Some(0),
+                'Y' => Some(1),
+                'Z' => Some(2),
+                'E' => Some(3),
+                _ => None,
+            };
+            match (code, letter, axis) {
+                (201, _, Some(axis)) => self.max_accel[axis] = value,
+                (203, _, Some(axis)) => self.max_feedrate[axis] = value,
+                (204, 'S', _) => {
+                    self.print_accel = value;
+                    self.travel_accel = value;
+                }
+                (204, 'P', _) => self.print_accel = value,
+                (204, 'R', _) => self.retract_accel = value,
+                (204, 'T', _) => self.travel_accel = value,
+                (205, 'J', _) => self.junction_deviation = Some(value),
+                (205, _, Some(axis)) => {
+                    self.jerk[axis] = value;
+                    // Firmware built with classic jerk reports X/Y jerk instead of J
+                    if axis < 2 {
+                        self.junction_deviation = None;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// One planner block: a straight move (XYZ and/or E) or a dwell.
+#[derive(Clone, Copy, Debug)]
+pub struct PlannerMove {
+    /// Axis deltas in mm: X, Y, Z, E
+    pub delta: [f32; AXES],
+    /// Requested feedrate in mm/s
+    pub feedrate: f32,
+    /// Fixed pause in seconds (G4); the toolhead is stopped before and after
+    pub dwell: f32,
+}
+
+/// A move after limits are applied, waiting in the lookahead window.
+struct Block {
+    distance: f32,
+    unit: [f32; AXES],
+    nominal: f32,
+    accel: f32,
+    /// Upper bound on the entry speed from the junction with the previous block
+    max_entry: f32,
+    entry: f32,
+    dwell: f32,
+}
+
+/// Blocks held back for lookahead before the oldest one's speed profile is fixed.
+///
+/// Firmware plans over a short buffer (16 blocks in stock Marlin); a longer window only
+/// matters for long runs of tiny segments and keeps memory constant on huge files.
+const LOOKAHEAD: usize = 64;
+
+/// Estimates move durations with a trapezoidal velocity profile, streaming.
+///
+/// Junction speeds follow the firmware's junction deviation or classic jerk model. Each
+/// new block triggers a backward pass over the window, stopping as soon as entry speeds
+/// stop changing; the forward pass runs as blocks leave the window.
+pub struct Planner {
+    pub limits: MotionLimits,
+    blocks: VecDeque<Block>,
+}
+
+impl Planner {
+    pub fn new() -> Self {
+        Self {
+            limits: MotionLimits::default(),
+            blocks: VecDeque::with_capacity(LOOKAHEAD + 1),
+        }
+    }
+
+    /// Queues a move; returns the duration of the oldest block once it leaves the window.
+    ///
+    /// Durations come out in the order moves went in, one per move.
+    pub fn push(&mut self, mv: PlannerMove) -> Option<f32> {
+        self.blocks.push_back(self.block(mv));
+        self.recalculate();
+        (self.blocks.len() > LOOKAHEAD).then(|| self.pop())
+    }
+
+    /// Durations of the blocks still in the window, with the toolhead stopping at the end.
+    pub fn finish(mut self) -> Vec<f32> {
+        let mut durations = Vec::with_capacity(self.blocks.len());
+        while !self.blocks.is_empty() {
+            durations.push(self.pop());
+        }
+        durations
+    }
+
+    fn block(&self, mv: PlannerMove) -> Block {
+        let limits = &self.limits;
+        let xyz = (mv.delta[0].powi(2) + mv.delta[1].powi(2) + mv.delta[2].powi(2)).sqrt();
+        let distance = if xyz > 0.0 { xyz } else { mv.delta[3].abs() };
+        if distance == 0.0 {
+            return Block {
+                distance: 0.0,
+                unit: [0.0; AXES],
+                nominal: 0.0,
+                accel: 1.0,
+                max_entry: 0.0,
+                entry: 0.0,
+                dwell: mv.dwell,
+            };
+        }
+
+        let mut unit = [0.0; AXES];
+        for (u, d) in unit.iter_mut().zip(mv.delta) {
+            *u = d / distance;
+        }
+
+        let mut nominal = mv.feedrate.max(0.1);
+        let mut accel = if xyz == 0.0 {
+            limits.retract_accel
+        } else if mv.delta[3] > 0.0 {
+            limits.print_accel
+        } else {
+            limits.travel_accel
+        };
+        for ((u, max_feedrate), max_accel) in unit.iter().zip(limits.max_feedrate).zip(limits.max_accel) {
+            let share = u.abs();
+            if share > 0.0 {
+                nominal = nominal.min(max_feedrate / share);
+                accel = accel.min(max_accel / share);
+            }
+        }
+
+        let max_entry = match self.blocks.back() {
+            Some(prev) if prev.distance > 0.0 => junction_speed(prev, &unit, nominal, accel, limits),
+            _ => start_speed(&unit, nominal, limits),
+        };
+
+        Block {
+            distance,
+            unit,
+            nominal,
+            accel,
+            max_entry,
+            entry: 0.0,
+            dwell: 0.0,
+        }
+    }
+
+    /// Backward pass: each block must be able to decelerate to the next block's entry
+    /// speed, and the newest block to a stop.
+    fn recalculate(&mut self) {
+        let mut next_entry = 0.0_f32;
+        for (i, block) in self.blocks.iter_mut().rev().enumerate() {
+            if block.distance == 0.0 {
+                // The toolhead stops at a dwell anyway, so nothing before it changes
+                break;
+            }
+            let entry = block
+                .max_entry
+                .min((next_entry * next_entry + 2.0 * block.accel * block.distance).sqrt());
+            if i > 0 && entry == block.entry {
+                break;
+            }
+            block.entry = entry;
+            next_entry = entry;
+        }
+    }
+
+    /// Forward pass for the oldest block: cap the next entry at what it can accelerate
+    /// to, then time it.
+    fn pop(&mut self) -> f32 {
+        let block = self.blocks.pop_front().expect("planner window is empty");
+        if block.distance == 0.0 {
+            return block.dwell;
+        }
+        let reachable = (block.entry * block.entry + 2.0 * block.accel * block.distance).sqrt();
+        let exit = match self.blocks.front_mut() {
+            Some(next) if next.distance > 0.0 => {
+                next.max_entry = next.max_entry.min(reachable);
+                next.entry = next.entry.min(reachable);
+                next.entry
+            }
+            _ => 0.0,
+        };
+        trapezoid_time(block.distance, block.entry, exit, block.nominal, block.accel)
+    }
+}
+
+/// Speed the toolhead may start a block at from standstill.
+fn start_speed(unit: &[f32; AXES], nominal: f32, limits: &MotionLimits) -> f32 {
+    if limits.junction_deviation.is_some() {
+        return 0.0;
+    }
+    let mut speed = nominal;
+    for (u, jerk) in unit.iter().zip(limits.jerk) {
+        if u.abs() * nominal > jerk {
+            speed = speed.min(jerk / u.abs());
+        }
+    }
+    speed
+}
+
+/// Maximum speed through the corner between `prev` and a block heading along `unit`.
+fn junction_speed(prev: &Block, unit: &[f32; AXES], nominal: f32, accel: f32, limits: &MotionLimits) -> f32 {
+    let cruise = prev.nominal.min(nominal);
+    match limits.junction_deviation {
+        Some(deviation) => {
+            // Only XYZ direction changes count towards the cornering angle
+            let cos_theta = -(0..3).map(|a| prev.unit[a] * unit[a]).sum::<f32>();
+            if cos_theta > 0.999_999 {
+                // Full reversal
+                return 0.0;
+            }
+            if cos_theta < -0.999_999 {
+                // Straight line
+                return cruise;
+            }
+            let sin_theta_d2 = (0.5 * (1.0 - cos_theta)).sqrt();
+            let speed_sq = accel * deviation * sin_theta_d2 / (1.0 - sin_theta_d2);
+            speed_sq.sqrt().min(cruise)
+        }
+        None => {
+            let mut factor = 1.0_f32;
+            for ((prev_u, u), max_jerk) in prev.unit.iter().zip(unit).zip(limits.jerk) {
+                let jerk = ((prev_u - u) * cruise).abs();
+                if jerk > max_jerk {
+                    factor = factor.min(max_jerk / jerk);
+                }
+            }
+            cruise * factor
+        }
+    }
+}
+
+/// Time to cover `distance` accelerating from `entry` towards `nominal` and ending at `exit`.
+fn trapezoid_time(distance: f32, entry: f32, exit: f32, nominal: f32, accel: f32) -> f32 {
+    let accel_dist = (nominal * nominal - entry * entry) / (2.0 * accel);
+    let decel_dist = (nominal * nominal - exit * exit) / (2.0 * accel);
+    if accel_dist + decel_dist <= distance {
+        (nominal - entry) / accel + (nominal - exit) / accel + (distance - accel_dist - decel_dist) / nominal
+    } else {
+        // Triangle profile: never reaches the nominal speed
+        let peak = ((2.0 * accel * distance + entry * entry + exit * exit) / 2.0).sqrt();
+        (peak - entry).max(0.0) / accel + (peak - exit).max(0.0) / accel
+    }
+}
+
+/// Parses a slicer time such as `1h 13m 59s` or `2d 3h 4m 5s` into seconds.
+pub fn parse_duration(text: &str) -> Option<f32> {
+    let mut total = 0.0;
+    let mut any = false;
+    for part in text.split_whitespace() {
+        let (number, unit) = part.split_at(part.find(|c: char| c.is_ascii_alphabetic())?);
+        let value: f32 = number.parse().ok()?;
+        total += value
+            * match unit {
+                "d" => 86400.0,
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                _ => return None,
+            };
+        any = true;
+    }
+    any.then_some(total)
+}
+
+/// Formats seconds the way slicers do, e.g. `1h 13m 59s`.
+pub fn format_duration(seconds: f32) -> String {
+    let total = seconds.round() as u64;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{}h {}m {}s", h, m, s)
+    } else if m > 0 {
+        format!("{}m {}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(unit: [f32; AXES], nominal: f32) -> Block {
+        Block { distance: 10.0, unit, nominal, accel: 1000.0, max_entry: nominal, entry: 0.0, dwell: 0.0 }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn trapezoid_reaching_nominal() {
+        // 0.5mm each to accelerate and brake, the rest at 10mm/s
+        assert_close(trapezoid_time(100.0, 0.0, 0.0, 10.0, 100.0), 0.1 + 0.1 + 9.9);
+    }
+
+    #[test]
+    fn trapezoid_triangle() {
+        // Peaks at 10mm/s halfway instead of reaching 100mm/s
+        assert_close(trapezoid_time(1.0, 0.0, 0.0, 100.0, 100.0), 0.2);
+    }
+
+    #[test]
+    fn junction_deviation_corners() {
+        let limits = MotionLimits::default();
+        let prev = block([1.0, 0.0, 0.0, 0.0], 50.0);
+        assert_close(junction_speed(&prev, &[1.0, 0.0, 0.0, 0.0], 40.0, 1000.0, &limits), 40.0);
+        assert_close(junction_speed(&prev, &[-1.0, 0.0, 0.0, 0.0], 40.0, 1000.0, &limits), 0.0);
+        let corner = junction_speed(&prev, &[0.0, 1.0, 0.0, 0.0], 40.0, 1000.0, &limits);
+        assert!(corner > 0.0 && corner < 40.0, "{}", corner);
+    }
+
+    #[test]
+    fn classic_jerk_corner() {
+        let limits = MotionLimits { junction_deviation: None, ..MotionLimits::default() };
+        let prev = block([1.0, 0.0, 0.0, 0.0], 50.0);
+        // A right angle changes X and Y by the full 50mm/s; 10mm/s of jerk allows a fifth of it
+        assert_close(junction_speed(&prev, &[0.0, 1.0, 0.0, 0.0], 50.0, 1000.0, &limits), 10.0);
+    }
+
+    #[test]
+    fn limits_from_m_codes() {
+        let mut limits = MotionLimits::default();
+        limits.apply(204, [('P', 1500.0), ('T', 2500.0)].into_iter());
+        limits.apply(205, [('X', 8.0)].into_iter());
+        assert_eq!((limits.print_accel, limits.travel_accel), (1500.0, 2500.0));
+        assert_eq!(limits.jerk[0], 8.0);
+        assert_eq!(limits.junction_deviation, None);
+    }
+
+    #[test]
+    fn planner_single_move() {
+        let mut planner = Planner::new();
+        let mv = PlannerMove { delta: [100.0, 0.0, 0.0, 0.0], feedrate: 10.0, dwell: 0.0 };
+        assert_eq!(planner.push(mv), None);
+        let durations = planner.finish();
+        assert_eq!(durations.len(), 1);
+        // Accelerating to 10mm/s at 3000mm/s² barely adds to the 10s at speed
+        assert!(durations[0] > 10.0 && durations[0] < 10.01, "{}", durations[0]);
+    }
+
+    #[test]
+    fn durations_round_trip() {
+        assert_eq!(parse_duration("1h 13m 59s"), Some(4439.0));
+        assert_eq!(parse_duration("2d 3h 4m 5s"), Some(2.0 * 86400.0 + 3.0 * 3600.0 + 4.0 * 60.0 + 5.0));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("12 minutes"), None);
+        assert_eq!(format_duration(4439.0), "1h 13m 59s");
+        assert_eq!(format_duration(65.4), "1m 5s");
+        assert_eq!(format_duration(5.0), "5s");
+    }
+}
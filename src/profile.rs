@@ -0,0 +1,65 @@
+/// Axis-aligned XY rectangle on the bed, in mm.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+impl Region {
+    /// Parses `X0,Y0,X1,Y1` as two opposite corners.
+    pub fn parse(text: &str) -> Option<Self> {
+        let values: Vec<f32> = text
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let [x0, y0, x1, y1] = values[..] else {
+            return None;
+        };
+        Some(Self {
+            min_x: x0.min(x1),
+            min_y: y0.min(y1),
+            max_x: x0.max(x1),
+            max_y: y0.max(y1),
+        })
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        (self.min_x..=self.max_x).contains(&x) && (self.min_y..=self.max_y).contains(&y)
+    }
+}
+
+/// Where a printer's stock start G-code lays its purge line.
+struct PrinterProfile {
+    /// Lowercase substrings of the slicer's `printer_model` setting
+    models: &'static [&'static str],
+    purge_region: Region,
+}
+
+const PROFILES: [PrinterProfile; 3] = [
+    // Creality: two lines along the left edge, X 0.1-0.4 from Y 20 to 200
+    PrinterProfile {
+        models: &["ender-3", "ender 3", "ender-5", "cr-10"],
+        purge_region: Region { min_x: 0.0, min_y: 0.0, max_x: 5.0, max_y: 235.0 },
+    },
+    // Prusa MK3/MK4: intro line in front of the bed at Y -3
+    PrinterProfile {
+        models: &["mk3", "mk4", "mk2.5"],
+        purge_region: Region { min_x: 0.0, min_y: -5.0, max_x: 250.0, max_y: 0.0 },
+    },
+    // Prusa MINI: intro line in front of the bed at Y -2
+    PrinterProfile {
+        models: &["mini"],
+        purge_region: Region { min_x: 0.0, min_y: -4.0, max_x: 180.0, max_y: 0.0 },
+    },
+];
+
+/// Purge region of the printer named by a `printer_model` setting, if it is one we know.
+pub fn purge_region(printer_model: &str) -> Option<Region> {
+    let model = printer_model.to_ascii_lowercase();
+    PROFILES
+        .iter()
+        .find(|profile| profile.models.iter().any(|m| model.contains(m)))
+        .map(|profile| profile.purge_region)
+}
@@ -0,0 +1,162 @@
+use crate::gpu::{Geometry, GpuVertex};
+use anyhow::{anyhow, Result};
+use macroquad::prelude::{vec3, Mat4, Vec3};
+
+/// Samples per output pixel along each axis, averaged down when saving.
+const SUPERSAMPLE: usize = 2;
+
+/// Software stand-in for the GPU pipeline, for rendering without a window.
+///
+/// Draws the same vertex/index geometry the viewer uploads, with the same depth test
+/// (less or equal, with writes) and alpha blending, into a supersampled RGB buffer.
+pub struct Raster {
+    width: usize,
+    height: usize,
+    color: Vec<Vec3>,
+    depth: Vec<f32>,
+}
+
+/// A vertex after projection: pixel position, depth in NDC and color with 0-1 channels.
+#[derive(Clone, Copy)]
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    color: Vec3,
+    alpha: f32,
+}
+
+impl Raster {
+    pub fn new(width: u32, height: u32, background: Vec3) -> Self {
+        let width = width as usize * SUPERSAMPLE;
+        let height = height as usize * SUPERSAMPLE;
+        Self {
+            width,
+            height,
+            color: vec![background; width * height],
+            depth: vec![f32::INFINITY; width * height],
+        }
+    }
+
+    /// Projects a vertex; `None` behind the camera, where the divide by w would flip it.
+    fn project(&self, mvp: &Mat4, vertex: &GpuVertex) -> Option<ScreenVertex> {
+        let clip = *mvp * Vec3::from_array(vertex.position).extend(1.0);
+        if clip.w <= 1e-6 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        let [r, g, b, a] = vertex.color;
+        Some(ScreenVertex {
+            x: (ndc.x + 1.0) * 0.5 * self.width as f32,
+            // NDC Y points up, rows go down
+            y: (1.0 - ndc.y) * 0.5 * self.height as f32,
+            z: ndc.z,
+            color: vec3(r as f32, g as f32, b as f32) / 255.0,
+            alpha: a as f32 / 255.0,
+        })
+    }
+
+    /// Depth-tests and blends one sample.
+    fn plot(&mut self, x: usize, y: usize, z: f32, color: Vec3, alpha: f32) {
+        let i = y * self.width + x;
+        if !(-1.0..=1.0).contains(&z) || z > self.depth[i] {
+            return;
+        }
+        self.depth[i] = z;
+        self.color[i] = color * alpha + self.color[i] * (1.0 - alpha);
+    }
+
+    /// Draws `indices` as triangles, interpolating color and depth across each one.
+    pub fn draw_triangles(&mut self, mvp: &Mat4, geometry: &Geometry, indices: &[u32]) {
+        for triangle in indices.chunks_exact(3) {
+            let project = |i: u32| self.project(mvp, &geometry.vertices[i as usize]);
+            let (Some(a), Some(b), Some(c)) = (project(triangle[0]), project(triangle[1]), project(triangle[2]))
+            else {
+                continue;
+            };
+            let area = edge(&a, &b, c.x, c.y);
+            if area.abs() < 1e-12 {
+                continue;
+            }
+
+            let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as usize;
+            let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as usize;
+            let max_x = (a.x.max(b.x).max(c.x).ceil() as usize).min(self.width);
+            let max_y = (a.y.max(b.y).max(c.y).ceil() as usize).min(self.height);
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    // Sample at the pixel center; either winding counts, as there is no culling
+                    let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                    let wa = edge(&b, &c, px, py) / area;
+                    let wb = edge(&c, &a, px, py) / area;
+                    let wc = 1.0 - wa - wb;
+                    if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                        continue;
+                    }
+                    let z = a.z * wa + b.z * wb + c.z * wc;
+                    let color = a.color * wa + b.color * wb + c.color * wc;
+                    let alpha = a.alpha * wa + b.alpha * wb + c.alpha * wc;
+                    self.plot(x, y, z, color, alpha);
+                }
+            }
+        }
+    }
+
+    /// Draws `indices` as line pairs about one output pixel wide.
+    pub fn draw_lines(&mut self, mvp: &Mat4, geometry: &Geometry, indices: &[u32]) {
+        for line in indices.chunks_exact(2) {
+            let project = |i: u32| self.project(mvp, &geometry.vertices[i as usize]);
+            let (Some(a), Some(b)) = (project(line[0]), project(line[1])) else {
+                continue;
+            };
+            let steps = (b.x - a.x).abs().max((b.y - a.y).abs()).ceil().max(1.0);
+            // Lines far outside the image would take forever to walk
+            if steps > (self.width + self.height) as f32 * 4.0 {
+                continue;
+            }
+            for step in 0..=steps as usize {
+                let t = step as f32 / steps;
+                let x = a.x + (b.x - a.x) * t;
+                let y = a.y + (b.y - a.y) * t;
+                let z = a.z + (b.z - a.z) * t;
+                let color = a.color.lerp(b.color, t);
+                let alpha = a.alpha + (b.alpha - a.alpha) * t;
+                // A square of samples as wide as one output pixel
+                let (left, top) = (x - SUPERSAMPLE as f32 * 0.5, y - SUPERSAMPLE as f32 * 0.5);
+                for sy in 0..SUPERSAMPLE {
+                    for sx in 0..SUPERSAMPLE {
+                        let (px, py) = ((left + sx as f32).round(), (top + sy as f32).round());
+                        if px >= 0.0 && py >= 0.0 && (px as usize) < self.width && (py as usize) < self.height {
+                            self.plot(px as usize, py as usize, z, color, alpha);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Averages the samples down to the output size and writes a PNG.
+    pub fn save_png(&self, path: &str) -> Result<()> {
+        let (width, height) = (self.width / SUPERSAMPLE, self.height / SUPERSAMPLE);
+        let mut bytes = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Vec3::ZERO;
+                for sy in 0..SUPERSAMPLE {
+                    for sx in 0..SUPERSAMPLE {
+                        sum += self.color[(y * SUPERSAMPLE + sy) * self.width + x * SUPERSAMPLE + sx];
+                    }
+                }
+                let average = sum / (SUPERSAMPLE * SUPERSAMPLE) as f32;
+                bytes.extend(average.to_array().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+            }
+        }
+        image::save_buffer(path, &bytes, width as u32, height as u32, image::ColorType::Rgb8)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path, e))
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `(x, y)`.
+fn edge(a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
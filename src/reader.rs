@@ -0,0 +1,101 @@
+use anyhow::{bail, Context, Result};
+use gcode::GCode;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::thread;
+
+/// Bytes handed to one tokenizer thread; chunks are extended to the next line break.
+const CHUNK_SIZE: usize = 4 << 20;
+
+/// One non-empty source line, tokenized.
+pub enum Line<'a> {
+    /// Full-line comment, without the leading `;`
+    Comment(&'a str),
+    Codes(Vec<GCode>),
+}
+
+/// Streams a G-code file through `handle` in file order, with each line's 1-based number.
+///
+/// The file is read in chunks and each batch of chunks is tokenized on all cores, so only
+/// one chunk per core is held in memory regardless of file size. `progress` gets the
+/// fraction of the file handled so far after each chunk and returns `false` to stop reading.
+pub fn read_lines(
+    filename: &str,
+    mut handle: impl FnMut(u32, Line),
+    mut progress: impl FnMut(f32) -> bool,
+) -> Result<()> {
+    let file = File::open(filename).context(format!("Failed to read file: {}", filename))?;
+    let total = file.metadata()?.len().max(1);
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, file);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut done = 0_u64;
+    let mut first_line = 1_u32;
+
+    loop {
+        let mut chunks = Vec::with_capacity(workers);
+        while chunks.len() < workers {
+            let chunk = read_chunk(&mut reader).context(format!("Failed to read file: {}", filename))?;
+            if chunk.is_empty() {
+                break;
+            }
+            chunks.push(chunk);
+        }
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        let tokenized: Vec<(Vec<(u32, Line)>, u32)> = thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .iter()
+                .map(|chunk| scope.spawn(|| tokenize(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("tokenizer thread panicked"))
+                .collect()
+        });
+
+        for (chunk, (lines, count)) in chunks.iter().zip(tokenized) {
+            for (offset, line) in lines {
+                handle(first_line + offset, line);
+            }
+            first_line += count;
+            done += chunk.len() as u64;
+            if !progress(done as f32 / total as f32) {
+                bail!("Loading cancelled");
+            }
+        }
+    }
+}
+
+/// Reads about `CHUNK_SIZE` bytes, ending on a line break; empty at end of file.
+fn read_chunk(reader: &mut BufReader<File>) -> std::io::Result<String> {
+    let mut buf = Vec::with_capacity(CHUNK_SIZE + 256);
+    reader.by_ref().take(CHUNK_SIZE as u64).read_to_end(&mut buf)?;
+    reader.read_until(b'\n', &mut buf)?;
+    // Stray non-UTF-8 bytes only ever show up in comments, so don't fail the whole file
+    Ok(String::from_utf8(buf).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()))
+}
+
+/// Tokenized non-empty lines with their offset from the chunk's first line, and the
+/// number of lines in the chunk.
+fn tokenize(chunk: &str) -> (Vec<(u32, Line<'_>)>, u32) {
+    let lines = chunk
+        .lines()
+        .enumerate()
+        .map(|(offset, line)| (offset as u32, line.trim()))
+        .filter(|(_, trimmed)| !trimmed.is_empty())
+        .map(|(offset, trimmed)| {
+            let line = if let Some(comment) = trimmed.strip_prefix(';') {
+                Line::Comment(comment)
+            } else {
+                let codes = gcode::parse(trimmed)
+                    .flat_map(|parsed| parsed.gcodes().to_vec())
+                    .collect();
+                Line::Codes(codes)
+            };
+            (offset, line)
+        })
+        .collect();
+    (lines, chunk.lines().count() as u32)
+}
@@ -0,0 +1,131 @@
+use crate::cli::SceneOptions;
+use crate::color_mode::Coloring;
+use crate::loader::LoadProgress;
+use crate::raster::Raster;
+use crate::{build_scene, build_scene_lines, build_scene_tubes, light_direction, Camera, Visibility, BACKGROUND};
+use anyhow::Result;
+use macroquad::prelude::vec3;
+
+/// Fixed camera directions for `render`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    /// The viewer's starting view, from above the back right corner
+    Iso,
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl View {
+    pub const ALL: [View; 7] = [
+        View::Iso,
+        View::Front,
+        View::Back,
+        View::Left,
+        View::Right,
+        View::Top,
+        View::Bottom,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            View::Iso => "iso",
+            View::Front => "front",
+            View::Back => "back",
+            View::Left => "left",
+            View::Right => "right",
+            View::Top => "top",
+            View::Bottom => "bottom",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|view| view.name() == name)
+    }
+
+    /// Camera yaw and pitch in radians; yaw 0 looks from the back (+Y) towards the front.
+    fn angles(self) -> (f32, f32) {
+        let (yaw, pitch): (f32, f32) = match self {
+            View::Iso => (45.0, 30.0),
+            View::Front => (180.0, 0.0),
+            View::Back => (0.0, 0.0),
+            View::Left => (-90.0, 0.0),
+            View::Right => (90.0, 0.0),
+            // Not quite vertical, which would leave the camera's up vector undefined
+            View::Top => (180.0, 89.9),
+            View::Bottom => (180.0, -89.9),
+        };
+        (yaw.to_radians(), pitch.to_radians())
+    }
+}
+
+/// What `gsoda render` was asked to do.
+pub struct RenderOptions {
+    pub file: String,
+    /// PNG file to write
+    pub output: String,
+    pub view: View,
+    pub width: u32,
+    pub height: u32,
+    /// Flat lines instead of volumetric tubes
+    pub lines: bool,
+    pub travels: bool,
+    /// Leave out the start/end G-code and frame the print itself
+    pub filter: bool,
+    pub scene: SceneOptions,
+}
+
+/// Renders the file to a PNG without a window or GPU.
+///
+/// The geometry, colors and camera are the viewer's, drawn by the software rasterizer.
+pub fn run(options: &RenderOptions) -> Result<()> {
+    let scene = build_scene(&options.file, options.scene, &LoadProgress::default())?;
+    let toolpath = &scene.toolpath;
+    let (bounds, range) = if options.filter {
+        (&scene.print_bounds, scene.print_range.clone())
+    } else {
+        (&scene.bounds, 0..toolpath.len())
+    };
+    let scale = 2.0 / bounds.max_dimension();
+    let light_dir = light_direction();
+
+    let mut camera = Camera::new(Camera::DEFAULT_DISTANCE);
+    (camera.yaw, camera.pitch) = options.view.angles();
+    let mvp = camera.matrix(options.width as f32 / options.height as f32);
+
+    let mut raster = Raster::new(options.width, options.height, vec3(BACKGROUND.r, BACKGROUND.g, BACKGROUND.b));
+    let (extrusions, travels) = build_scene_lines(toolpath, Coloring::Feature, &Visibility::all(), bounds, scale, light_dir);
+    if options.lines {
+        raster.draw_lines(&mvp, &extrusions, extrusions.indices_in(&range));
+    } else {
+        let filament_area = std::f32::consts::PI * (scene.filament_diameter * 0.5).powi(2);
+        let tubes = build_scene_tubes(
+            toolpath,
+            &scene.layers,
+            Coloring::Feature,
+            &Visibility::all(),
+            bounds,
+            scale,
+            filament_area,
+            light_dir,
+        );
+        raster.draw_triangles(&mvp, &tubes, tubes.indices_in(&range));
+    }
+    // Travels are translucent, so they go over the extrusions like in the viewer
+    if options.travels {
+        raster.draw_lines(&mvp, &travels, travels.indices_in(&range));
+    }
+
+    raster.save_png(&options.output)?;
+    println!(
+        "Wrote {} ({}x{}, {} view)",
+        options.output,
+        options.width,
+        options.height,
+        options.view.name()
+    );
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use crate::gpu::{Geometry, GpuVertex};
+use crate::{to_scene, Toolpath, Vec3D};
+use macroquad::prelude::*;
+
+/// What happened to the filament at a [`RetractEvent`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetractKind {
+    /// E moved backwards in a G0-G3 move
+    Retract,
+    /// E moved forwards without the nozzle moving
+    Unretract,
+    /// G10
+    FirmwareRetract,
+    /// G11
+    FirmwareUnretract,
+}
+
+impl RetractKind {
+    pub const ALL: [RetractKind; 4] = [
+        RetractKind::Retract,
+        RetractKind::Unretract,
+        RetractKind::FirmwareRetract,
+        RetractKind::FirmwareUnretract,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RetractKind::Retract => "Retract",
+            RetractKind::Unretract => "Unretract",
+            RetractKind::FirmwareRetract => "G10 retract",
+            RetractKind::FirmwareUnretract => "G11 unretract",
+        }
+    }
+
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            RetractKind::Retract => (255, 60, 200),
+            RetractKind::Unretract => (60, 220, 255),
+            RetractKind::FirmwareRetract => (255, 160, 40),
+            RetractKind::FirmwareUnretract => (120, 255, 120),
+        }
+    }
+
+    pub fn is_retract(self) -> bool {
+        matches!(self, RetractKind::Retract | RetractKind::FirmwareRetract)
+    }
+}
+
+/// A retraction or unretraction, which moves filament without laying down a bead.
+#[derive(Clone, Copy, Debug)]
+pub struct RetractEvent {
+    pub kind: RetractKind,
+    /// Nozzle position where it started, in G-code coordinates
+    pub position: Vec3D,
+    /// In mm of E; 0 for firmware retraction when the file sets no M207 length
+    pub length: f32,
+    /// Index of the segment that follows it, so events follow layer filtering and playback
+    pub segment: usize,
+}
+
+/// Appends an event, folding it into the previous one when that is the same kind with no
+/// extrusion in between, as when a slicer spreads one retraction over several wipe moves.
+pub fn record(events: &mut Vec<RetractEvent>, toolpath: &Toolpath, event: RetractEvent) {
+    if let Some(last) = events.last_mut() {
+        if last.kind == event.kind && !toolpath.segments[last.segment..].iter().any(|s| s.is_extrusion()) {
+            last.length += event.length;
+            return;
+        }
+    }
+    events.push(event);
+}
+
+/// Wireframe glyphs in scene coordinates, grouped by the segment each event precedes:
+/// a diamond for retractions and a cross for unretractions, colored by kind.
+pub fn build_markers(events: &[RetractEvent], center: Vec3D, scale: f32) -> Geometry {
+    const SIZE: f32 = 0.012;
+    // Octahedron edges over the six axis points +X, -X, +Y, -Y, +Z, -Z
+    const DIAMOND: [u32; 24] = [0, 2, 1, 2, 4, 2, 5, 2, 0, 3, 1, 3, 4, 3, 5, 3, 0, 4, 4, 1, 1, 5, 5, 0];
+    const CROSS: [u32; 6] = [0, 1, 2, 3, 4, 5];
+
+    let mut geometry = Geometry::default();
+    for event in events {
+        let (r, g, b) = event.kind.rgb();
+        let color = Color::from_rgba(r, g, b, 255);
+        let p = to_scene(event.position, center, scale);
+        geometry.begin_segment(event.segment);
+        let base = geometry.base();
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            geometry.vertices.push(GpuVertex::new(p + axis * SIZE, color));
+            geometry.vertices.push(GpuVertex::new(p - axis * SIZE, color));
+        }
+        let edges: &[u32] = if event.kind.is_retract() { &DIAMOND } else { &CROSS };
+        geometry.indices.extend(edges.iter().map(|i| base + i));
+    }
+    geometry
+}
@@ -0,0 +1,106 @@
+use crate::metadata::Metadata;
+use macroquad::prelude::*;
+
+const PANEL_WIDTH: f32 = 560.0;
+const ROW_HEIGHT: f32 = 18.0;
+const KEY_CHARS: usize = 30;
+const VALUE_CHARS: usize = 38;
+
+/// Searchable list of the file's slicer settings over the right side of the window.
+///
+/// Typing filters by key or value; the typed settings are listed first, highlighted.
+#[derive(Default)]
+pub struct SettingsPanel {
+    search: String,
+    /// Index of the first row shown
+    scroll: usize,
+}
+
+impl SettingsPanel {
+    /// Handles typing and scrolling; false once Esc closes the panel.
+    pub fn update(&mut self, metadata: &Metadata) -> bool {
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() {
+                self.search.push(c);
+                self.scroll = 0;
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.search.pop();
+            self.scroll = 0;
+        }
+
+        let page = visible_rows();
+        let (_, wheel_y) = mouse_wheel();
+        if wheel_y > 0.0 || is_key_pressed(KeyCode::Up) {
+            self.scroll = self.scroll.saturating_sub(if wheel_y > 0.0 { 3 } else { 1 });
+        }
+        if wheel_y < 0.0 || is_key_pressed(KeyCode::Down) {
+            self.scroll += if wheel_y < 0.0 { 3 } else { 1 };
+        }
+        if is_key_pressed(KeyCode::PageUp) {
+            self.scroll = self.scroll.saturating_sub(page);
+        }
+        if is_key_pressed(KeyCode::PageDown) {
+            self.scroll += page;
+        }
+        self.scroll = self.scroll.min(self.rows(metadata).len().saturating_sub(page));
+
+        !is_key_pressed(KeyCode::Escape)
+    }
+
+    pub fn draw(&self, metadata: &Metadata) {
+        let x = screen_width() - PANEL_WIDTH - 10.0;
+        let top = 40.0;
+        let height = screen_height() - top - 40.0;
+        draw_rectangle(x, top, PANEL_WIDTH, height, Color::from_rgba(25, 25, 38, 235));
+
+        let rows = self.rows(metadata);
+        draw_text(
+            format!("Settings ({}): {}_   (type to search, Esc=Close)", rows.len(), self.search),
+            x + 10.0,
+            top + 22.0,
+            20.0,
+            WHITE,
+        );
+        if rows.is_empty() {
+            draw_text("No matching settings", x + 10.0, top + 50.0, 18.0, GRAY);
+        }
+        for (i, (key, value, typed)) in rows.iter().skip(self.scroll).take(visible_rows()).enumerate() {
+            let y = top + 50.0 + i as f32 * ROW_HEIGHT;
+            let color = if *typed { Color::from_rgba(130, 210, 255, 255) } else { LIGHTGRAY };
+            draw_text(truncate(key, KEY_CHARS), x + 10.0, y, 16.0, color);
+            draw_text(truncate(value, VALUE_CHARS), x + 250.0, y, 16.0, WHITE);
+        }
+    }
+
+    /// Matching rows as (key, value, is a typed setting).
+    fn rows(&self, metadata: &Metadata) -> Vec<(String, String, bool)> {
+        let query = self.search.to_lowercase();
+        let summary = metadata
+            .summary()
+            .into_iter()
+            .filter(|(label, value)| {
+                label.to_lowercase().contains(&query) || value.to_lowercase().contains(&query)
+            })
+            .map(|(label, value)| (label.to_owned(), value, true));
+        let settings = metadata
+            .matching(&self.search)
+            .map(|s| (s.key.clone(), s.value.clone(), false));
+        summary.chain(settings).collect()
+    }
+}
+
+fn visible_rows() -> usize {
+    ((screen_height() - 130.0) / ROW_HEIGHT).max(1.0) as usize
+}
+
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_owned()
+    } else {
+        let mut short: String = text.chars().take(max_chars - 3).collect();
+        short.push_str("...");
+        short
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::ops::Range;
+
+/// Lines between the offsets kept in a [`SourceIndex`].
+const STRIDE: u32 = 64;
+
+/// Where every `STRIDE`th line of a file starts, so any line can be read back without
+/// holding the file in memory.
+pub struct SourceIndex {
+    filename: String,
+    /// Byte offset of lines 1, 1 + STRIDE, 1 + 2 * STRIDE, ...
+    checkpoints: Vec<u64>,
+    lines: u32,
+}
+
+impl SourceIndex {
+    pub fn build(filename: &str) -> Result<Self> {
+        let file = File::open(filename).context(format!("Failed to read file: {}", filename))?;
+        let mut reader = BufReader::with_capacity(1 << 20, file);
+        let mut checkpoints = vec![0];
+        let mut lines = 0_u32;
+        let mut offset = 0_u64;
+        let mut ends_with_break = true;
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            for (i, _) in buffer.iter().enumerate().filter(|(_, &b)| b == b'\n') {
+                lines += 1;
+                if lines.is_multiple_of(STRIDE) {
+                    checkpoints.push(offset + i as u64 + 1);
+                }
+            }
+            ends_with_break = buffer.last() == Some(&b'\n');
+            let len = buffer.len();
+            offset += len as u64;
+            reader.consume(len);
+        }
+        if !ends_with_break {
+            lines += 1;
+        }
+        Ok(Self { filename: filename.to_owned(), checkpoints, lines })
+    }
+
+    /// Number of lines in the file.
+    pub fn line_count(&self) -> u32 {
+        self.lines
+    }
+
+    /// Text of the 1-based lines in `range` that exist, without line breaks.
+    pub fn read(&self, range: Range<u32>) -> Result<Vec<String>> {
+        let range = range.start.max(1)..range.end.min(self.lines + 1);
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let checkpoint = (range.start - 1) / STRIDE;
+        let mut file = File::open(&self.filename).context(format!("Failed to read file: {}", self.filename))?;
+        file.seek(SeekFrom::Start(self.checkpoints[checkpoint as usize]))?;
+
+        let mut reader = BufReader::new(file);
+        let mut text = Vec::new();
+        let mut lines = Vec::with_capacity(range.len());
+        for number in checkpoint * STRIDE + 1..range.end {
+            text.clear();
+            if reader.read_until(b'\n', &mut text)? == 0 {
+                break;
+            }
+            if number >= range.start {
+                lines.push(String::from_utf8_lossy(&text).trim_end().to_owned());
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Text of one 1-based line, or why it couldn't be read.
+    pub fn line(&self, number: u32) -> String {
+        match self.read(number..number + 1) {
+            Ok(lines) => lines
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| format!("({} has no line {})", self.filename, number)),
+            Err(err) => format!("({:#})", err),
+        }
+    }
+}
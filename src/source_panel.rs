@@ -0,0 +1,141 @@
+use crate::settings_panel::truncate;
+use crate::source::SourceIndex;
+use macroquad::prelude::*;
+
+const PANEL_WIDTH: f32 = 520.0;
+const TOP: f32 = 40.0;
+const ROW_HEIGHT: f32 = 18.0;
+const TEXT_CHARS: usize = 54;
+
+/// Scrollable view of the G-code text over the right side of the window.
+///
+/// Only the lines on screen are read from the file, and again only when the view moves.
+pub struct SourcePanel {
+    /// First line shown, 1-based
+    scroll: u32,
+    /// Line clicked in the panel or of the picked segment
+    selected: Option<u32>,
+    /// Line of the last move playback revealed
+    cursor: Option<u32>,
+    /// Text of the lines from `scroll`, as last read
+    lines: Vec<String>,
+    /// Scroll position and row count `lines` were read for
+    read_for: Option<(u32, usize)>,
+}
+
+impl SourcePanel {
+    pub fn new() -> Self {
+        Self {
+            scroll: 1,
+            selected: None,
+            cursor: None,
+            lines: Vec::new(),
+            read_for: None,
+        }
+    }
+
+    /// Left edge of the panel, which anything drawn beside it stays left of.
+    pub fn left() -> f32 {
+        screen_width() - PANEL_WIDTH - 10.0
+    }
+
+    pub fn contains((x, y): (f32, f32)) -> bool {
+        x >= Self::left() && (TOP..screen_height() - 40.0).contains(&y)
+    }
+
+    /// Scrolls with the wheel over the panel; returns the line clicked, if any.
+    pub fn update(&mut self, source: &SourceIndex) -> Option<u32> {
+        let position = mouse_position();
+        if !Self::contains(position) {
+            return None;
+        }
+        let (_, wheel_y) = mouse_wheel();
+        if wheel_y > 0.0 {
+            self.scroll = self.scroll.saturating_sub(3).max(1);
+        } else if wheel_y < 0.0 {
+            self.scroll += 3;
+        }
+        self.scroll = self.scroll.min(last_scroll(source));
+
+        let row = (position.1 - TOP - 35.0) / ROW_HEIGHT;
+        let line = self.scroll + row.max(0.0) as u32;
+        let on_line = (0.0..visible_rows() as f32).contains(&row) && line <= source.line_count();
+        (is_mouse_button_pressed(MouseButton::Left) && on_line).then(|| {
+            self.selected = Some(line);
+            line
+        })
+    }
+
+    /// Highlights `line` and scrolls it into view.
+    pub fn select(&mut self, line: u32, source: &SourceIndex) {
+        self.selected = Some(line);
+        self.follow(line, source);
+    }
+
+    /// Marks the playback cursor's line, following it as it moves.
+    pub fn set_cursor(&mut self, line: Option<u32>, source: &SourceIndex) {
+        if line != self.cursor {
+            self.cursor = line;
+            if let Some(line) = line {
+                self.follow(line, source);
+            }
+        }
+    }
+
+    /// Scrolls just enough to bring `line` into view, a few rows from the edge.
+    fn follow(&mut self, line: u32, source: &SourceIndex) {
+        let rows = visible_rows() as u32;
+        let margin = (rows / 4).min(5);
+        if line < self.scroll + margin {
+            self.scroll = line.saturating_sub(margin).max(1);
+        } else if line + margin >= self.scroll + rows {
+            self.scroll = (line + margin + 1).saturating_sub(rows).max(1);
+        }
+        self.scroll = self.scroll.min(last_scroll(source));
+    }
+
+    /// Draws the visible lines, marking the selected one and the playback cursor's.
+    pub fn draw(&mut self, source: &SourceIndex) {
+        let rows = visible_rows();
+        if self.read_for != Some((self.scroll, rows)) {
+            let first = self.scroll;
+            self.lines = source
+                .read(first..first + rows as u32)
+                .unwrap_or_else(|err| vec![format!("({:#})", err)]);
+            self.read_for = Some((first, rows));
+        }
+
+        let x = Self::left();
+        let height = screen_height() - TOP - 40.0;
+        draw_rectangle(x, TOP, PANEL_WIDTH, height, Color::from_rgba(25, 25, 38, 235));
+        let last = self.scroll + self.lines.len().max(1) as u32 - 1;
+        draw_text(
+            format!("G-code: lines {}-{} of {}   (G=Close)", self.scroll, last, source.line_count()),
+            x + 10.0,
+            TOP + 22.0,
+            20.0,
+            WHITE,
+        );
+        for (i, text) in self.lines.iter().enumerate() {
+            let line = self.scroll + i as u32;
+            let top = TOP + 35.0 + i as f32 * ROW_HEIGHT;
+            if Some(line) == self.selected {
+                draw_rectangle(x, top, PANEL_WIDTH, ROW_HEIGHT, Color::from_rgba(120, 110, 30, 200));
+            } else if Some(line) == self.cursor {
+                draw_rectangle(x, top, PANEL_WIDTH, ROW_HEIGHT, Color::from_rgba(40, 80, 120, 200));
+            }
+            let color = if text.trim_start().starts_with(';') { GRAY } else { WHITE };
+            draw_text(format!("{:>7}", line), x + 4.0, top + 14.0, 16.0, DARKGRAY);
+            draw_text(truncate(text, TEXT_CHARS), x + 64.0, top + 14.0, 16.0, color);
+        }
+    }
+}
+
+fn visible_rows() -> usize {
+    ((screen_height() - TOP - 85.0) / ROW_HEIGHT).max(1.0) as usize
+}
+
+/// Scroll position that shows the last line at the bottom.
+fn last_scroll(source: &SourceIndex) -> u32 {
+    (source.line_count() + 1).saturating_sub(visible_rows() as u32).max(1)
+}
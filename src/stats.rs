@@ -0,0 +1,173 @@
+use crate::feature::FeatureType;
+use crate::metadata::Metadata;
+use crate::tool::ToolChanges;
+use crate::{Layer, Toolpath};
+use std::f32::consts::PI;
+use std::ops::AddAssign;
+
+/// Relative difference from the slicer's filament length that suggests a parsing problem.
+pub const FILAMENT_TOLERANCE: f32 = 0.02;
+
+/// Filament used by some part of a print.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    /// Filament length in mm
+    pub length: f32,
+    /// In mm³
+    pub volume: f32,
+    /// In g; zero without a filament density
+    pub weight: f32,
+    /// Zero without a filament price
+    pub cost: f32,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.length += other.length;
+        self.volume += other.volume;
+        self.weight += other.weight;
+        self.cost += other.cost;
+    }
+}
+
+/// How much filament the extrusions use, in total and per layer, feature and tool.
+///
+/// Only the E of extrusion moves and of flushing counts, so retractions and the unretracts
+/// that undo them cancel out the way they do on the spool.
+pub struct FilamentStats {
+    pub total: Usage,
+    /// Indexed like the scene's layers
+    pub per_layer: Vec<Usage>,
+    /// Indexed by `FeatureType as usize`
+    pub per_feature: [Usage; FeatureType::COUNT],
+    /// Indexed by tool number, up to the highest tool used
+    pub per_tool: Vec<Usage>,
+    /// Part of `per_tool` that went into the prime tower or was flushed during tool changes
+    pub purge_per_tool: Vec<Usage>,
+    /// The file states a filament density, so weights are known
+    pub has_weight: bool,
+    /// The file or command line states a price, so costs are known
+    pub has_cost: bool,
+    /// The slicer's total length in mm, to check ours against
+    pub slicer_length: Option<f32>,
+}
+
+impl FilamentStats {
+    /// Densities and prices come per filament slot from the metadata; tools past the
+    /// listed ones use the last entry.
+    ///
+    /// Flushed filament has no segment, so it only adds to the totals and per-tool figures.
+    pub fn compute(
+        toolpath: &Toolpath,
+        layers: &[Layer],
+        tool_changes: &ToolChanges,
+        filament_diameter: f32,
+        metadata: &Metadata,
+    ) -> Self {
+        let area = PI * (filament_diameter * 0.5).powi(2);
+        let densities = metadata.numbers(&["filament_density", "material_density"]);
+        let prices = metadata.numbers(&["filament_cost"]);
+        let per_slot = |values: &[f32], tool: usize| values.get(tool).or(values.last()).copied().unwrap_or(0.0);
+        let usage = |e: f32, tool: usize| {
+            let volume = e * area;
+            // g/cm³ × mm³ / 1000 = g
+            let weight = volume * per_slot(&densities, tool) / 1000.0;
+            Usage {
+                length: e,
+                volume,
+                weight,
+                cost: weight / 1000.0 * per_slot(&prices, tool),
+            }
+        };
+
+        let mut stats = Self {
+            total: Usage::default(),
+            per_layer: vec![Usage::default(); layers.len()],
+            per_feature: [Usage::default(); FeatureType::COUNT],
+            per_tool: Vec::new(),
+            purge_per_tool: Vec::new(),
+            has_weight: !densities.is_empty(),
+            has_cost: !densities.is_empty() && !prices.is_empty(),
+            slicer_length: metadata.total_filament_length,
+        };
+
+        // Layers are contiguous, so walk them alongside the segments
+        let mut layer = 0;
+        for (index, seg) in toolpath.segments.iter().enumerate() {
+            while layer + 1 < layers.len() && layers[layer].range.end <= index {
+                layer += 1;
+            }
+            if !seg.is_extrusion() {
+                continue;
+            }
+
+            let tool = seg.tool as usize;
+            let usage = usage(seg.e, tool);
+            stats.total += usage;
+            if let Some(layer_usage) = stats.per_layer.get_mut(layer) {
+                *layer_usage += usage;
+            }
+            stats.per_feature[seg.feature() as usize] += usage;
+            stats.add_tool(tool, usage, seg.feature() == FeatureType::PrimeTower);
+        }
+        for (tool, &e) in tool_changes.flush_e.iter().enumerate() {
+            if e > 0.0 {
+                let usage = usage(e, tool);
+                stats.total += usage;
+                stats.add_tool(tool, usage, true);
+            }
+        }
+        stats
+    }
+
+    fn add_tool(&mut self, tool: usize, usage: Usage, purge: bool) {
+        if self.per_tool.len() <= tool {
+            self.per_tool.resize(tool + 1, Usage::default());
+            self.purge_per_tool.resize(tool + 1, Usage::default());
+        }
+        self.per_tool[tool] += usage;
+        if purge {
+            self.purge_per_tool[tool] += usage;
+        }
+    }
+
+    /// Recomputes every cost at one price per kg, e.g. from the command line.
+    pub fn reprice(&mut self, price_per_kg: f32) {
+        let buckets = std::iter::once(&mut self.total)
+            .chain(&mut self.per_layer)
+            .chain(&mut self.per_feature)
+            .chain(&mut self.per_tool)
+            .chain(&mut self.purge_per_tool);
+        for usage in buckets {
+            usage.cost = usage.weight / 1000.0 * price_per_kg;
+        }
+        self.has_cost = self.has_weight;
+    }
+
+    /// Relative difference between our total length and the slicer's, if it states one.
+    pub fn slicer_deviation(&self) -> Option<f32> {
+        let slicer = self.slicer_length.filter(|&l| l > 0.0)?;
+        Some((self.total.length - slicer) / slicer)
+    }
+
+    /// Length, volume and whatever of weight and cost is known, e.g. `4.31m 10.36cm³ 13.16g 0.39`.
+    pub fn describe(&self, usage: &Usage) -> String {
+        let mut text = format!("{} {:.2}cm³", format_length(usage.length), usage.volume / 1000.0);
+        if self.has_weight {
+            text += &format!(" {:.2}g", usage.weight);
+        }
+        if self.has_cost {
+            text += &format!(" {:.2}", usage.cost);
+        }
+        text
+    }
+}
+
+/// Filament length in mm as `850mm` or `4.31m`.
+pub fn format_length(mm: f32) -> String {
+    if mm < 1000.0 {
+        format!("{:.0}mm", mm)
+    } else {
+        format!("{:.2}m", mm / 1000.0)
+    }
+}
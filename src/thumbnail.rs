@@ -0,0 +1,134 @@
+use crate::reader::{self, Line};
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+
+/// Image encoding of an embedded thumbnail.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThumbnailFormat {
+    Png,
+    Jpg,
+    Qoi,
+}
+
+impl ThumbnailFormat {
+    /// Format named by the tag in front of `begin`: `thumbnail` (Bambu/Orca, Prusa,
+    /// Cura/Klipper), `thumbnail_PNG`/`_JPG`/`_QOI` (PrusaSlicer) or `png`/`jpg` (Cura plugins).
+    fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "thumbnail" | "thumbnail_PNG" | "png" => Some(Self::Png),
+            "thumbnail_JPG" | "jpg" => Some(Self::Jpg),
+            "thumbnail_QOI" => Some(Self::Qoi),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpg => "jpg",
+            Self::Qoi => "qoi",
+        }
+    }
+}
+
+/// A preview image the slicer embedded as base64 comment lines.
+#[derive(Clone, Debug)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub format: ThumbnailFormat,
+    /// Decoded image file contents
+    pub data: Vec<u8>,
+}
+
+/// Block being read: its `begin` tag, header and base64 text so far.
+struct OpenBlock {
+    tag: String,
+    format: ThumbnailFormat,
+    width: u32,
+    height: u32,
+    base64: String,
+}
+
+/// Gathers thumbnails from comment lines as they stream past.
+///
+/// Every slicer writes the same shape, `<tag> begin WxH LEN`, base64 lines, `<tag> end`;
+/// Bambu's `THUMBNAIL_BLOCK_START`/`_END` just wraps that, so it needs no handling of its own.
+#[derive(Default)]
+pub struct ThumbnailCollector {
+    open: Option<OpenBlock>,
+    thumbnails: Vec<Thumbnail>,
+}
+
+impl ThumbnailCollector {
+    /// Takes a trimmed comment; true if it belonged to a thumbnail block.
+    pub fn push_comment(&mut self, comment: &str) -> bool {
+        if let Some(block) = &mut self.open {
+            if comment.strip_suffix(" end") == Some(block.tag.as_str()) {
+                let block = self.open.take().unwrap();
+                // A damaged block is dropped rather than failing the whole file
+                if let Ok(data) = STANDARD.decode(&block.base64) {
+                    self.thumbnails.push(Thumbnail {
+                        width: block.width,
+                        height: block.height,
+                        format: block.format,
+                        data,
+                    });
+                }
+            } else {
+                block.base64.push_str(comment);
+            }
+            return true;
+        }
+
+        let Some((tag, header)) = comment.split_once(" begin") else {
+            return false;
+        };
+        let Some(format) = ThumbnailFormat::from_tag(tag) else {
+            return false;
+        };
+        let (width, height) = header
+            .split_whitespace()
+            .next()
+            .and_then(|size| size.split_once('x'))
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .unwrap_or((0, 0));
+        self.open = Some(OpenBlock {
+            tag: tag.to_owned(),
+            format,
+            width,
+            height,
+            base64: String::new(),
+        });
+        true
+    }
+
+    /// Thumbnails in file order; a block missing its `end` line is dropped.
+    pub fn finish(self) -> Vec<Thumbnail> {
+        self.thumbnails
+    }
+}
+
+/// Largest thumbnail in a format the viewer can show.
+pub fn best_png(thumbnails: &[Thumbnail]) -> Option<&Thumbnail> {
+    thumbnails
+        .iter()
+        .filter(|t| t.format == ThumbnailFormat::Png)
+        .max_by_key(|t| t.width * t.height)
+}
+
+/// Reads only the thumbnails out of a G-code file.
+pub fn extract(filename: &str) -> Result<Vec<Thumbnail>> {
+    let mut collector = ThumbnailCollector::default();
+    reader::read_lines(
+        filename,
+        |_, line| {
+            if let Line::Comment(comment) = line {
+                collector.push_comment(comment.trim());
+            }
+        },
+        |_| true,
+    )?;
+    Ok(collector.finish())
+}
@@ -0,0 +1,53 @@
+use crate::metadata::Metadata;
+
+/// Tool numbers from here up are firmware special cases (Bambu's T255, T1000), not extruders.
+pub const MAX_TOOLS: usize = 16;
+
+/// Colors for tool slots the file gives no filament color for.
+const FALLBACK_COLORS: [(u8, u8, u8); 8] = [
+    (255, 125, 56),
+    (77, 160, 255),
+    (90, 220, 110),
+    (240, 64, 160),
+    (255, 230, 77),
+    (150, 84, 204),
+    (0, 200, 200),
+    (200, 200, 200),
+];
+
+/// Tool changes and purging seen while parsing, indexed by tool number.
+#[derive(Clone, Debug, Default)]
+pub struct ToolChanges {
+    /// Switches to each tool once something has been extruded; selecting the first tool isn't a change
+    pub counts: [u32; MAX_TOOLS],
+    /// Filament each tool pushed out without moving inside Bambu/Orca's `; FLUSH_START` /
+    /// `; FLUSH_END` blocks, in mm of E
+    pub flush_e: [f32; MAX_TOOLS],
+}
+
+impl ToolChanges {
+    pub fn total(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+}
+
+/// Filament color of every tool slot, from the file's `filament_colour` list where it has one.
+pub fn colors(metadata: &Metadata) -> [(u8, u8, u8); MAX_TOOLS] {
+    std::array::from_fn(|tool| {
+        metadata
+            .filament_colors
+            .get(tool)
+            .and_then(|color| parse_hex(color))
+            .unwrap_or(FALLBACK_COLORS[tool % FALLBACK_COLORS.len()])
+    })
+}
+
+/// `#RRGGBB`, or `#RRGGBBAA` as Bambu writes some colors, ignoring the alpha.
+fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.trim().strip_prefix('#')?;
+    if hex.len() != 6 && hex.len() != 8 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
@@ -0,0 +1,145 @@
+use crate::{Layer, Toolpath, LAYER_Z_EPSILON};
+use std::ops::Range;
+
+/// What a non-extruding move is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TravelKind {
+    /// Level G1 move between extrusions
+    Travel,
+    /// Lift above the extrusions before and after a travel, or the drop back down from it
+    ZHop,
+    /// Z move to the next extrusion's height
+    LayerChange,
+    /// Move between `; WIPE_START` and `; WIPE_END`
+    Wipe,
+    /// Level G0 move
+    Rapid,
+}
+
+impl TravelKind {
+    pub const COUNT: usize = 5;
+
+    pub const ALL: [TravelKind; TravelKind::COUNT] = [
+        TravelKind::Travel,
+        TravelKind::ZHop,
+        TravelKind::LayerChange,
+        TravelKind::Wipe,
+        TravelKind::Rapid,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TravelKind::Travel => "Travel",
+            TravelKind::ZHop => "Z-hop",
+            TravelKind::LayerChange => "Layer change",
+            TravelKind::Wipe => "Wipe",
+            TravelKind::Rapid => "Rapid (G0)",
+        }
+    }
+
+    /// Base color for the kind, before lighting and height shading.
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            TravelKind::Travel => (255, 100, 100),
+            TravelKind::ZHop => (255, 220, 80),
+            TravelKind::LayerChange => (200, 120, 255),
+            TravelKind::Wipe => (90, 200, 255),
+            TravelKind::Rapid => (255, 150, 60),
+        }
+    }
+}
+
+/// Sorts out wipes, z-hops and layer changes among the travels the parser tagged as
+/// G0 or G1 moves.
+///
+/// Z moves are judged by the run of travels between two extrusions of `print_range`, so this
+/// runs once the print section is known. A run that climbs above the extrusions at both its
+/// ends is a z-hop, and its Z moves above that level are the lift and the drop; any other
+/// Z move is a layer change. Moves outside `print_range`, like the start and end G-code, and
+/// after its last extrusion keep their G0/G1 kind.
+pub fn classify(toolpath: &mut Toolpath, print_range: Range<usize>) {
+    for seg in &mut toolpath.segments {
+        if !seg.is_extrusion() && seg.is_wipe() {
+            seg.travel = TravelKind::Wipe;
+        }
+    }
+
+    let mut index = print_range.start;
+    while index < print_range.end {
+        if toolpath.segments[index].is_extrusion() {
+            index += 1;
+            continue;
+        }
+        let Some(next) = (index..print_range.end).find(|&i| toolpath.segments[i].is_extrusion()) else {
+            break;
+        };
+        let level = toolpath.start(index).z.max(toolpath.start(next).z);
+        let peak = (index..next).map(|i| toolpath.end(i).z).fold(f32::NEG_INFINITY, f32::max);
+        let hop = peak > level + LAYER_Z_EPSILON;
+        for i in index..next {
+            let (start, end) = (toolpath.start(i), toolpath.end(i));
+            let seg = &mut toolpath.segments[i];
+            if seg.is_wipe() || (end.z - start.z).abs() <= LAYER_Z_EPSILON {
+                continue;
+            }
+            seg.travel = if hop && start.z.max(end.z) > level + LAYER_Z_EPSILON {
+                TravelKind::ZHop
+            } else {
+                TravelKind::LayerChange
+            };
+        }
+        index = next;
+    }
+}
+
+/// Travel distances and moves per kind, and z-hop lifts per layer, over a range of segments.
+pub struct TravelStats {
+    /// In mm, indexed by `TravelKind as usize`
+    pub distance: [f32; TravelKind::COUNT],
+    pub moves: [usize; TravelKind::COUNT],
+    /// Indexed like the scene's layers; a lift split into arc chords counts once
+    pub z_hops_per_layer: Vec<u32>,
+}
+
+impl TravelStats {
+    pub fn compute(toolpath: &Toolpath, layers: &[Layer], range: Range<usize>) -> Self {
+        let mut stats = Self {
+            distance: [0.0; TravelKind::COUNT],
+            moves: [0; TravelKind::COUNT],
+            z_hops_per_layer: vec![0; layers.len()],
+        };
+        let mut lifting = false;
+        for (layer_index, layer) in layers.iter().enumerate() {
+            for index in layer.range.start.max(range.start)..layer.range.end.min(range.end) {
+                let seg = &toolpath.segments[index];
+                if seg.is_extrusion() {
+                    lifting = false;
+                    continue;
+                }
+                stats.distance[seg.travel as usize] += toolpath.length(index);
+                stats.moves[seg.travel as usize] += 1;
+
+                let rising = seg.travel == TravelKind::ZHop && toolpath.end(index).z > toolpath.start(index).z;
+                if rising && !lifting {
+                    stats.z_hops_per_layer[layer_index] += 1;
+                }
+                lifting = rising;
+            }
+        }
+        stats
+    }
+
+    /// Kinds with at least one move, in legend order.
+    pub fn present(&self) -> Vec<TravelKind> {
+        TravelKind::ALL.into_iter().filter(|&kind| self.moves[kind as usize] > 0).collect()
+    }
+
+    pub fn total_distance(&self) -> f32 {
+        self.distance.iter().sum()
+    }
+
+    pub fn z_hops(&self) -> u32 {
+        self.z_hops_per_layer.iter().sum()
+    }
+}
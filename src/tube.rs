@@ -0,0 +1,108 @@
+use crate::gpu::{Geometry, GpuVertex};
+use macroquad::prelude::*;
+
+/// Vertices per ring of the elliptic tube cross-section, from the right side over the top.
+const RING: usize = 8;
+
+/// Miter joints on sharp corners are capped at this multiple of the half width.
+const MAX_MITER: f32 = 2.0;
+
+/// One extrusion bead to turn into a tube, already in scene coordinates.
+pub struct Bead {
+    /// Index of the segment in the toolpath
+    pub segment: usize,
+    pub start: Vec3,
+    pub end: Vec3,
+    pub width: f32,
+    pub height: f32,
+    /// Unlit color, channels 0-1
+    pub color: Vec3,
+}
+
+/// Builds shaded tube geometry for a run of beads in file order.
+///
+/// Consecutive beads that share an end point are joined with a mitered ring so corners
+/// close up; free ends get a flat cap. Lighting is baked into the vertex colors.
+pub fn build_tubes(beads: &[Bead], light_dir: Vec3) -> Geometry {
+    let mut geometry = Geometry::default();
+
+    for (i, bead) in beads.iter().enumerate() {
+        // Consecutive segments meeting at the same point form one continuous bead
+        let prev = i
+            .checked_sub(1)
+            .map(|p| &beads[p])
+            .filter(|p| p.segment + 1 == bead.segment && p.end == bead.start);
+        let next = beads
+            .get(i + 1)
+            .filter(|n| bead.segment + 1 == n.segment && n.start == bead.end);
+
+        let dir = (bead.end - bead.start).normalize_or_zero();
+        let side = side_vector(dir);
+        let start_side = prev.map_or(side, |p| miter(side_vector((p.end - p.start).normalize_or_zero()), side));
+        let end_side = next.map_or(side, |n| miter(side, side_vector((n.end - n.start).normalize_or_zero())));
+
+        geometry.begin_segment(bead.segment);
+        let base = geometry.base();
+        push_ring(&mut geometry, bead, bead.start, start_side, light_dir);
+        push_ring(&mut geometry, bead, bead.end, end_side, light_dir);
+
+        let ring = RING as u32;
+        for k in 0..ring {
+            let k1 = (k + 1) % ring;
+            let (a, a1, b, b1) = (base + k, base + k1, base + ring + k, base + ring + k1);
+            geometry.indices.extend_from_slice(&[a, b, b1, a, b1, a1]);
+        }
+        // Caps are fans around the ring's first vertex
+        for k in 1..ring - 1 {
+            if prev.is_none() {
+                geometry.indices.extend_from_slice(&[base, base + k + 1, base + k]);
+            }
+            if next.is_none() {
+                let b = base + ring;
+                geometry.indices.extend_from_slice(&[b, b + k, b + k + 1]);
+            }
+        }
+    }
+
+    geometry
+}
+
+/// Horizontal unit vector across the bead; the scene's up axis is +Y.
+fn side_vector(dir: Vec3) -> Vec3 {
+    let side = dir.cross(Vec3::Y);
+    if side.length_squared() < 1e-12 {
+        Vec3::X
+    } else {
+        side.normalize()
+    }
+}
+
+/// Side vector for the ring shared by two beads, stretched so both tubes keep their width.
+fn miter(a: Vec3, b: Vec3) -> Vec3 {
+    let bisector = (a + b).normalize_or_zero();
+    let cos = bisector.dot(b);
+    if cos <= 1.0 / MAX_MITER {
+        return b;
+    }
+    bisector / cos
+}
+
+/// Pushes the elliptic cross-section around `point`, which is the nozzle tip at the top of the bead.
+///
+/// The ellipse spans the full bead width and height, so neighbouring beads meet along their
+/// sides the way printed ones do, and gaps or overlaps between them show.
+fn push_ring(geometry: &mut Geometry, bead: &Bead, point: Vec3, side: Vec3, light_dir: Vec3) {
+    let (half_width, half_height) = (bead.width * 0.5, bead.height * 0.5);
+    let center = point - Vec3::Y * half_height;
+    let side_unit = side.normalize_or_zero();
+    for k in 0..RING {
+        let (sin, cos) = (k as f32 * std::f32::consts::TAU / RING as f32).sin_cos();
+        let position = center + side * (half_width * cos) + Vec3::Y * (half_height * sin);
+        // The gradient of the ellipse equation points straight out of its surface
+        let normal = (side_unit * (cos / half_width.max(1e-6)) + Vec3::Y * (sin / half_height.max(1e-6)))
+            .normalize_or_zero();
+        let lighting = 0.45 + 0.55 * normal.dot(light_dir).max(0.0);
+        let c = bead.color * lighting;
+        geometry.vertices.push(GpuVertex::new(position, Color::new(c.x, c.y, c.z, 1.0)));
+    }
+}
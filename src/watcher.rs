@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the file's modification time is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when a file is rewritten on disk, e.g. by a slicer exporting over it.
+///
+/// Polls the modification time, and only reports a change once it has held still for a
+/// poll interval so a file that is still being written isn't picked up half way.
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// Modification time seen on the last poll that differs from `modified`
+    pending: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            modified: modified_time(&path),
+            pending: None,
+            last_poll: Instant::now(),
+            path,
+        }
+    }
+
+    /// True once per change, after the file has settled.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        // A missing file is usually being replaced; wait for the new one
+        let Some(current) = modified_time(&self.path) else {
+            return false;
+        };
+        if Some(current) == self.modified {
+            self.pending = None;
+            return false;
+        }
+        if self.pending != Some(current) {
+            self.pending = Some(current);
+            return false;
+        }
+        self.modified = Some(current);
+        self.pending = None;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}